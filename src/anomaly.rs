@@ -0,0 +1,193 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::date_time::DateTime;
+use crate::timeseries::{Bucket, Sample, TimeSeries};
+
+// What an anomaly deviates in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyKind {
+    VolumeSpike, // many more entries than usual
+    VolumeDrop,  // many fewer entries than usual, e.g. a service that went quiet
+    ErrorSpike,  // many more ERROR + FATAL entries than usual
+}
+
+impl fmt::Display for AnomalyKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AnomalyKind::VolumeSpike => "volume spike",
+            AnomalyKind::VolumeDrop => "volume drop",
+            AnomalyKind::ErrorSpike => "error spike",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// How unusual a bucket has to be to be flagged.
+#[derive(Debug, Clone, Copy)]
+pub struct AnomalyConfig {
+    pub window: usize,       // preceding buckets that form the baseline
+    pub min_baseline: usize, // buckets needed before anything is scored
+    pub threshold: f64,      // robust z-score from which a bucket is flagged
+    pub min_count: usize,    // spikes smaller than this are ignored, however unusual
+}
+
+impl Default for AnomalyConfig {
+    fn default() -> Self {
+        AnomalyConfig {
+            window: 30,
+            min_baseline: 5,
+            threshold: 3.5,
+            min_count: 5,
+        }
+    }
+}
+
+// A run of consecutive flagged buckets of the same kind.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Anomaly {
+    pub kind: AnomalyKind,
+    pub start: DateTime, // start of the first bucket
+    pub end: DateTime,   // end of the last bucket (exclusive)
+    pub observed: usize, // entries (or errors) in the run
+    pub expected: f64,   // what the baselines predicted for the same buckets
+    pub score: f64,      // largest robust z-score in the run; negative for drops
+    pub top_components: Vec<(String, usize)>, // components contributing the most, largest first
+    pub samples: Vec<Sample>,
+}
+
+// Components listed per anomaly.
+const TOP_COMPONENTS: usize = 3;
+// Sample messages listed per anomaly.
+const SAMPLES: usize = 3;
+
+// Flags buckets of the series whose entry count, or ERROR + FATAL count, is far
+// from the rolling baseline of the `window` buckets before it, and joins
+// consecutive flagged buckets into one anomaly.
+//
+// The baseline is the median of those buckets and the spread their median
+// absolute deviation (scaled to match a standard deviation), so earlier bursts
+// inside the window barely move it. Since these are counts, the spread is at
+// least the square root of the median (and at least 1), so a perfectly flat
+// baseline does not turn every small change into an anomaly.
+pub fn detect_anomalies(series: &TimeSeries, config: &AnomalyConfig) -> Vec<Anomaly> {
+    let mut anomalies: Vec<Anomaly> = Vec::new();
+    let mut totals: VecDeque<f64> = VecDeque::with_capacity(config.window + 1);
+    let mut errors: VecDeque<f64> = VecDeque::with_capacity(config.window + 1);
+    let width = series.width().seconds();
+
+    // The run being built: (anomaly, buckets in it)
+    let mut open: Vec<(Anomaly, Vec<Bucket>)> = Vec::new();
+
+    for bucket in series.buckets() {
+        let mut flagged = Vec::new();
+        if totals.len() >= config.min_baseline.max(1) {
+            let (total, error_count) = (bucket.total as f64, bucket.errors() as f64);
+            let (median, spread) = baseline(&totals);
+            let score = (total - median) / spread;
+            if score >= config.threshold && bucket.total >= config.min_count {
+                flagged.push((AnomalyKind::VolumeSpike, bucket.total, median, score));
+            } else if score <= -config.threshold {
+                flagged.push((AnomalyKind::VolumeDrop, bucket.total, median, score));
+            }
+
+            let (median, spread) = baseline(&errors);
+            let score = (error_count - median) / spread;
+            if score >= config.threshold && bucket.errors() >= config.min_count {
+                flagged.push((AnomalyKind::ErrorSpike, bucket.errors(), median, score));
+            }
+        }
+
+        // Runs of kinds not flagged in this bucket are finished
+        let (mut continued, finished): (Vec<_>, Vec<_>) =
+            open.drain(..).partition(|(anomaly, _)| flagged.iter().any(|(kind, ..)| *kind == anomaly.kind));
+        anomalies.extend(finished.into_iter().map(|(anomaly, buckets)| finish(anomaly, &buckets, width)));
+
+        for (kind, observed, expected, score) in flagged {
+            match continued.iter_mut().find(|(anomaly, _)| anomaly.kind == kind) {
+                Some((anomaly, buckets)) => {
+                    anomaly.observed += observed;
+                    anomaly.expected += expected;
+                    if score.abs() > anomaly.score.abs() {
+                        anomaly.score = score;
+                    }
+                    buckets.push(bucket.clone());
+                }
+                None => continued.push((
+                    Anomaly {
+                        kind,
+                        start: bucket.start.clone(),
+                        end: bucket.start.clone(),
+                        observed,
+                        expected,
+                        score,
+                        top_components: Vec::new(),
+                        samples: Vec::new(),
+                    },
+                    vec![bucket.clone()],
+                )),
+            }
+        }
+        open = continued;
+
+        totals.push_back(bucket.total as f64);
+        errors.push_back(bucket.errors() as f64);
+        if totals.len() > config.window {
+            totals.pop_front();
+            errors.pop_front();
+        }
+    }
+    anomalies.extend(open.into_iter().map(|(anomaly, buckets)| finish(anomaly, &buckets, width)));
+
+    anomalies.sort_by(|a, b| a.start.cmp(&b.start).then(a.kind.cmp(&b.kind)));
+    anomalies
+}
+
+// Median and robust spread of the baseline window.
+fn baseline(window: &VecDeque<f64>) -> (f64, f64) {
+    let center = median(window.iter().copied().collect());
+    let mad = median(window.iter().map(|v| (v - center).abs()).collect());
+    (center, (1.4826 * mad).max(center.sqrt()).max(1.0))
+}
+
+fn median(mut values: Vec<f64>) -> f64 {
+    values.sort_by(f64::total_cmp);
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
+
+// Fills in the end, components and samples of a run from its buckets.
+fn finish(mut anomaly: Anomaly, buckets: &[Bucket], width: i64) -> Anomaly {
+    let last = buckets.last().expect("a run has at least one bucket");
+    anomaly.end = DateTime::from_unix_seconds(last.start.unix_seconds() + width).unwrap_or_else(|_| last.start.clone());
+
+    let mut components: BTreeMap<&str, usize> = BTreeMap::new();
+    for bucket in buckets {
+        let counts = match anomaly.kind {
+            AnomalyKind::ErrorSpike => &bucket.errors_by_component,
+            _ => &bucket.by_component,
+        };
+        for (component, count) in counts {
+            *components.entry(component).or_insert(0) += count;
+        }
+    }
+    let mut components: Vec<(String, usize)> = components.into_iter().map(|(c, n)| (c.to_string(), n)).collect();
+    // Largest first; BTreeMap order makes ties alphabetical
+    components.sort_by_key(|(_, count)| Reverse(*count));
+    components.truncate(TOP_COMPONENTS);
+    anomaly.top_components = components;
+
+    // Each bucket keeps its most severe entries; take the most severe of the run
+    let mut samples: Vec<&Sample> = buckets.iter().flat_map(|bucket| bucket.samples.iter()).collect();
+    samples.sort_by_key(|sample| (Reverse(sample.level), &sample.timestamp));
+    anomaly.samples = samples.into_iter().take(SAMPLES).cloned().collect();
+    anomaly
+}
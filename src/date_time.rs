@@ -1,8 +1,12 @@
 use std::str::FromStr;
 use std::fmt;
 use std::cmp::Ordering;
+use std::time::Duration;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
-#[derive(Debug,PartialEq,Eq,Clone)]
+// Wall-clock time as written in the log, plus the UTC offset it was written in.
+// A timestamp without a zone designator is treated as UTC.
+#[derive(Debug,Clone)]
 pub struct DateTime {
     pub year: u16,
     pub month: u8,
@@ -10,13 +14,230 @@ pub struct DateTime {
     pub hour: u8,
     pub minute: u8,
     pub second: u8,
+    pub nanosecond: u32,
+    pub offset_minutes: i16, // east of UTC, e.g. +02:00 => 120
 }
 
+pub fn is_leap_year(year: u16) -> bool {
+    (year.is_multiple_of(4) && !year.is_multiple_of(100)) || year.is_multiple_of(400)
+}
+
+pub fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        _ => 0,
+    }
+}
+
+// Last supported instant, 9999-12-31 23:59:59 UTC, in Unix seconds.
+const MAX_UNIX_SECONDS: i64 = 253_402_300_799;
+
+// Days since 1970-01-01 for a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: u8, day: u8) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let m = month as i64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+// Inverse of days_from_civil.
+fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = if month <= 2 { yoe + era * 400 + 1 } else { yoe + era * 400 };
+    (year, month, day)
+}
+
+impl DateTime {
+    // Seconds since the Unix epoch (UTC) and the sub-second part.
+    fn instant(&self) -> (i64, u32) {
+        let days = days_from_civil(self.year as i64, self.month, self.day);
+        let local = days * 86_400 + self.hour as i64 * 3_600 + self.minute as i64 * 60 + self.second as i64;
+        (local - self.offset_minutes as i64 * 60, self.nanosecond)
+    }
+
+    // Builds the wall-clock time for an instant as seen at the given offset.
+    // Fails if the instant or the local date falls outside the years 1970..=9999.
+    fn from_instant(secs: i64, nanosecond: u32, offset_minutes: i16) -> Result<DateTime, String> {
+        if !(0..=MAX_UNIX_SECONDS).contains(&secs) {
+            return Err("year Invalid".to_string());
+        }
+        let local = secs + offset_minutes as i64 * 60;
+        let (year, month, day) = civil_from_days(local.div_euclid(86_400));
+        if !(1970..=9999).contains(&year) {
+            return Err("year Invalid".to_string());
+        }
+        let rem = local.rem_euclid(86_400);
+        Ok(DateTime {
+            year: year as u16,
+            month,
+            day,
+            hour: (rem / 3_600) as u8,
+            minute: (rem % 3_600 / 60) as u8,
+            second: (rem % 60) as u8,
+            nanosecond,
+            offset_minutes,
+        })
+    }
+
+    // Same instant expressed with a zero UTC offset.
+    pub fn to_utc(&self) -> DateTime {
+        let (secs, nanosecond) = self.instant();
+        let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+        let rem = secs.rem_euclid(86_400);
+        DateTime {
+            year: year as u16,
+            month,
+            day,
+            hour: (rem / 3_600) as u8,
+            minute: (rem % 3_600 / 60) as u8,
+            second: (rem % 60) as u8,
+            nanosecond,
+            offset_minutes: 0,
+        }
+    }
+
+    pub fn from_unix_seconds(secs: i64) -> Result<DateTime, String> {
+        Self::from_instant(secs, 0, 0)
+    }
+
+    pub fn from_unix_millis(millis: i64) -> Result<DateTime, String> {
+        let nanosecond = (millis.rem_euclid(1_000) * 1_000_000) as u32;
+        Self::from_instant(millis.div_euclid(1_000), nanosecond, 0)
+    }
+
+    // Whole seconds since 1970-01-01 00:00:00 UTC (sub-second part truncated).
+    pub fn unix_seconds(&self) -> i64 {
+        self.instant().0
+    }
+
+    pub fn unix_millis(&self) -> i64 {
+        let (secs, nanos) = self.instant();
+        secs * 1_000 + (nanos / 1_000_000) as i64
+    }
+
+    fn unix_nanos(&self) -> i128 {
+        let (secs, nanos) = self.instant();
+        secs as i128 * 1_000_000_000 + nanos as i128
+    }
+
+    fn from_unix_nanos(nanos: i128, offset_minutes: i16) -> Option<DateTime> {
+        let secs = i64::try_from(nanos.div_euclid(1_000_000_000)).ok()?;
+        let nanosecond = nanos.rem_euclid(1_000_000_000) as u32;
+        Self::from_instant(secs, nanosecond, offset_minutes).ok()
+    }
+
+    // Adds a duration, keeping the UTC offset. None if the result is out of range.
+    pub fn checked_add(&self, duration: Duration) -> Option<DateTime> {
+        Self::from_unix_nanos(self.unix_nanos() + duration.as_nanos() as i128, self.offset_minutes)
+    }
+
+    pub fn checked_sub(&self, duration: Duration) -> Option<DateTime> {
+        Self::from_unix_nanos(self.unix_nanos() - duration.as_nanos() as i128, self.offset_minutes)
+    }
+
+    // Time elapsed from `earlier` to `self`, or None if `earlier` is later.
+    pub fn duration_since(&self, earlier: &DateTime) -> Option<Duration> {
+        let diff = self.unix_nanos() - earlier.unix_nanos();
+        if diff < 0 {
+            return None;
+        }
+        Some(Duration::new((diff / 1_000_000_000) as u64, (diff % 1_000_000_000) as u32))
+    }
+
+    // Absolute time between two timestamps, whichever comes first.
+    pub fn abs_diff(&self, other: &DateTime) -> Duration {
+        self.duration_since(other)
+            .or_else(|| other.duration_since(self))
+            .unwrap_or_default()
+    }
+}
+
+// Parses a whole number of seconds, minutes, hours or days: "30s", "5m", "2h", "1d".
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let invalid = || format!("invalid duration '{}': expected e.g. 30s, 5m, 1h or 1d", s);
+    let split = s.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+    let (count, unit) = s.split_at(split);
+    let count: u64 = count.parse().map_err(|_| invalid())?;
+    let unit_seconds = match unit.to_ascii_lowercase().as_str() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86_400,
+        _ => return Err(invalid()),
+    };
+    count.checked_mul(unit_seconds).map(Duration::from_secs).ok_or_else(invalid)
+}
+
+// Whole seconds as days, hours, minutes and seconds, leaving out zero parts: "1h 5m", "45s".
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let parts = [(secs / 86_400, "d"), (secs / 3600 % 24, "h"), (secs / 60 % 60, "m"), (secs % 60, "s")];
+    let shown: Vec<String> = parts.iter().filter(|(n, _)| *n > 0).map(|(n, unit)| format!("{}{}", n, unit)).collect();
+    if shown.is_empty() {
+        "0s".to_string()
+    } else {
+        shown.join(" ")
+    }
+}
+
+// Parses "Z", "+HH:MM", "+HHMM" or "+HH" into minutes east of UTC.
+fn parse_offset(s: &str) -> Result<i16, String> {
+    if s == "Z" || s == "z" {
+        return Ok(0);
+    }
+    let sign: i16 = match s.chars().next() {
+        Some('+') => 1,
+        Some('-') => -1,
+        _ => return Err("Invalid UTC offset".to_string()),
+    };
+    let digits = &s[1..];
+    let (h, m) = match digits.split_once(':') {
+        Some((h, m)) => (h, m),
+        None if digits.len() == 4 => digits.split_at(2),
+        None if digits.len() == 2 => (digits, "00"),
+        None => return Err("Invalid UTC offset".to_string()),
+    };
+    if h.len() != 2 || m.len() != 2 {
+        return Err("Invalid UTC offset".to_string());
+    }
+    let h: i16 = h.parse().map_err(|_| "error parsing offset hour")?;
+    let m: i16 = m.parse().map_err(|_| "error parsing offset minute")?;
+    if h > 23 || m > 59 {
+        return Err("offset Invalid".to_string());
+    }
+    Ok(sign * (h * 60 + m))
+}
+
+// Parses a fraction of a second with 1 to 9 digits into nanoseconds.
+fn parse_fraction(s: &str) -> Result<u32, String> {
+    if s.is_empty() || s.len() > 9 || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return Err("Invalid fractional seconds".to_string());
+    }
+    let value: u32 = s.parse().map_err(|_| "error parsing fractional seconds")?;
+    Ok(value * 10u32.pow(9 - s.len() as u32))
+}
+
+// Accepts "YYYY-MM-DD HH:MM:SS" as well as ISO 8601 / RFC 3339 variants such as
+// "2024-01-15T10:23:45.123+02:00" and "2024-01-15T10:23:45Z".
 impl FromStr for DateTime {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let (date, time) = s.split_once(' ')
+        let (date, time) = s.split_once([' ', 'T', 't'])
                                         .ok_or_else(|| "Invalid format in date and time".to_string())?;
 
         let (year, month, day) = {
@@ -30,10 +251,21 @@ impl FromStr for DateTime {
             }
         };
 
+        // Split off the zone designator, if any
+        let (time, offset_minutes) = match time.find(['Z', 'z', '+', '-']) {
+            Some(idx) => (&time[..idx], parse_offset(&time[idx..])?),
+            None => (time, 0),
+        };
+
+        let (time, nanosecond) = match time.split_once(['.', ',']) {
+            Some((time, fraction)) => (time, parse_fraction(fraction)?),
+            None => (time, 0),
+        };
+
         let (hour, minute, second) = {
             let mut it = time.split(':');
             match (it.next(), it.next(), it.next(), it.next()) {
-                (Some(h), Some(m), Some(s), None) 
+                (Some(h), Some(m), Some(s), None)
                 if !h.is_empty() && !m.is_empty() && !s.is_empty() => {
                     (h, m, s)
                 }
@@ -49,13 +281,13 @@ impl FromStr for DateTime {
         let minute: u8 = minute.parse().map_err(|_| "error parsing minute")?;
         let second: u8 = second.parse().map_err(|_| "error parsing second")?;
 
-        if year < 1970 || year > 9999 {
+        if !(1970..=9999).contains(&year) {
             return Err("year Invalid".to_string());
         }
-        if month < 1 || month > 12{
+        if !(1..=12).contains(&month) {
             return Err("month Invalid".to_string());
         }
-        if day < 1 || day > 31{
+        if day < 1 || day > days_in_month(year, month) {
             return Err("day Invalid".to_string());
         }
         if hour > 23{
@@ -67,14 +299,21 @@ impl FromStr for DateTime {
         if second > 59{
             return Err("second Invalid".to_string());
         }
-        Ok(DateTime {
+        let dt = DateTime {
             year,
             month,
             day,
             hour,
             minute,
             second,
-        })
+            nanosecond,
+            offset_minutes,
+        };
+        // The offset can move the instant out of range, e.g. 1970-01-01 00:10:00+01:00
+        if !(0..=MAX_UNIX_SECONDS).contains(&dt.unix_seconds()) {
+            return Err("year Invalid".to_string());
+        }
+        Ok(dt)
     }
 }
 
@@ -82,18 +321,57 @@ impl fmt::Display for DateTime {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "{}-{}-{} {}:{}:{}",
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
             self.year, self.month, self.day, self.hour, self.minute, self.second
-        )
+        )?;
+
+        if self.nanosecond != 0 {
+            if self.nanosecond.is_multiple_of(1_000_000) {
+                write!(f, ".{:03}", self.nanosecond / 1_000_000)?;
+            } else if self.nanosecond.is_multiple_of(1_000) {
+                write!(f, ".{:06}", self.nanosecond / 1_000)?;
+            } else {
+                write!(f, ".{:09}", self.nanosecond)?;
+            }
+        }
+
+        if self.offset_minutes != 0 {
+            let sign = if self.offset_minutes < 0 { '-' } else { '+' };
+            let abs = self.offset_minutes.unsigned_abs();
+            write!(f, "{}{:02}:{:02}", sign, abs / 60, abs % 60)?;
+        }
+        Ok(())
     }
 }
 
+// Serialized as its Display form, which keeps the fraction and the offset, so it
+// reads back to the same wall-clock time.
+impl Serialize for DateTime {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for DateTime {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+    }
+}
+
+// Two timestamps are equal when they denote the same instant, whatever their offsets.
+impl PartialEq for DateTime {
+    fn eq(&self, other: &Self) -> bool {
+        self.instant() == other.instant()
+    }
+}
+
+impl Eq for DateTime {}
+
 impl Ord for DateTime {
 
     fn cmp(&self, other:&Self) -> Ordering{
 
-        (self.year, self.month, self.day, self.hour, self.minute, self.second)
-            .cmp(&(other.year, other.month, other.day, other.hour, other.minute, other.second))
+        self.instant().cmp(&other.instant())
 
     }
 }
@@ -0,0 +1,56 @@
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use bzip2::bufread::MultiBzDecoder;
+use flate2::bufread::MultiGzDecoder;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+impl Compression {
+    // Recognises compressed data by its leading magic bytes. None if `bytes` is
+    // too short to tell.
+    pub fn from_magic(bytes: &[u8]) -> Option<Compression> {
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            Some(Compression::Gzip)
+        } else if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Compression::Zstd)
+        } else if bytes.starts_with(b"BZh") {
+            Some(Compression::Bzip2)
+        } else if bytes.len() >= 4 {
+            Some(Compression::None)
+        } else {
+            None
+        }
+    }
+
+    pub fn from_extension(path: &Path) -> Compression {
+        match path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase).as_deref() {
+            Some("gz") | Some("gzip") => Compression::Gzip,
+            Some("zst") | Some("zstd") => Compression::Zstd,
+            Some("bz2") => Compression::Bzip2,
+            _ => Compression::None,
+        }
+    }
+}
+
+// Wraps `reader` so that compressed input is decompressed as it is read.
+// The format is taken from the magic bytes when enough are buffered, otherwise
+// from the file extension. Nothing is consumed while sniffing, so pipes work too.
+pub fn decompressed<R: BufRead + Send + 'static>(mut reader: R, path: &Path) -> io::Result<Box<dyn BufRead + Send>> {
+    let head = reader.fill_buf()?;
+    let compression = Compression::from_magic(head)
+        .unwrap_or_else(|| if head.is_empty() { Compression::None } else { Compression::from_extension(path) });
+
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(reader))),
+        Compression::Zstd => Box::new(BufReader::new(zstd::stream::read::Decoder::with_buffer(reader)?)),
+        Compression::Bzip2 => Box::new(BufReader::new(MultiBzDecoder::new(reader))),
+    })
+}
@@ -0,0 +1,71 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::log_entry::{ParseError, ParseErrorKind};
+
+// Renders parse errors compiler-style, grouped by kind (largest group first):
+//
+// error[unknown-level]: 2 occurrences (showing 1)
+//   --> tests/fixtures/malformed.log:5
+//    |
+//  5 | 2024-01-15 10:00:00 [UNKNOWN] bad: Invalid level
+//    |                     ^^^^^^^^^ Invalid log level: Log Level is invalid
+//
+// At most `max_per_kind` examples are printed for each kind.
+pub fn render_diagnostics(errors: &[ParseError], max_per_kind: usize) -> String {
+    let mut groups: BTreeMap<ParseErrorKind, Vec<&ParseError>> = BTreeMap::new();
+    for err in errors {
+        groups.entry(err.kind).or_default().push(err);
+    }
+
+    let mut groups: Vec<(ParseErrorKind, Vec<&ParseError>)> = groups.into_iter().collect();
+    groups.sort_by(|a, b| b.1.len().cmp(&a.1.len()).then(a.0.cmp(&b.0)));
+
+    let mut out = String::new();
+    for (kind, group) in &groups {
+        let shown = group.len().min(max_per_kind);
+        let noun = if group.len() == 1 { "occurrence" } else { "occurrences" };
+        let _ = writeln!(out, "error[{}]: {} {} (showing {})", kind, group.len(), noun, shown);
+
+        for err in group.iter().take(shown) {
+            render_snippet(&mut out, err);
+        }
+        if group.len() > shown {
+            let _ = writeln!(out, "  = {} more {} errors not shown", group.len() - shown, kind);
+        }
+        out.push('\n');
+    }
+
+    if !groups.is_empty() {
+        let _ = writeln!(out, "parse error summary: {} errors", errors.len());
+        for (kind, group) in &groups {
+            let _ = writeln!(out, "  {:<22} {}", kind.as_str(), group.len());
+        }
+    }
+    out
+}
+
+fn render_snippet(out: &mut String, err: &ParseError) {
+    let gutter = err.line_number.to_string().len();
+    let pad = " ".repeat(gutter);
+    // Tabs would throw the caret column off, so show them as single spaces
+    let content = err.content.replace('\t', " ");
+
+    let _ = writeln!(out, "{}--> {}:{}", pad, err.file.display(), err.line_number);
+    let _ = writeln!(out, "{} |", pad);
+    let _ = writeln!(out, "{} | {}", err.line_number, content);
+
+    match &err.span {
+        Some(span) => {
+            let start = span.start.min(err.content.len());
+            let end = span.end.clamp(start, err.content.len());
+            // Spans are byte offsets; carets are placed per character
+            let column = err.content.get(..start).map(|s| s.chars().count()).unwrap_or(0);
+            let width = err.content.get(start..end).map(|s| s.chars().count()).unwrap_or(0).max(1);
+            let _ = writeln!(out, "{} | {}{} {}", pad, " ".repeat(column), "^".repeat(width), err.reason);
+        }
+        None => {
+            let _ = writeln!(out, "{} = {}", pad, err.reason);
+        }
+    }
+}
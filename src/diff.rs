@@ -0,0 +1,159 @@
+use std::collections::BTreeSet;
+
+use serde::Serialize;
+
+use crate::statistics_aggregator::Statistics;
+use crate::templates::TemplateMiner;
+
+// One count in both runs.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CountChange {
+    pub name: String,
+    pub before: usize,
+    pub after: usize,
+}
+
+impl CountChange {
+    pub fn delta(&self) -> i64 {
+        self.after as i64 - self.before as i64
+    }
+}
+
+// A component whose share of all entries moved by more than chance would explain.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ShareChange {
+    pub component: String,
+    pub before: f64, // fraction of all entries, 0.0 - 1.0
+    pub after: f64,
+    pub z_score: f64, // two-proportion z-test; positive when the share grew
+}
+
+// A message template seen in only one of the runs.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TemplateChange {
+    pub template: String,
+    pub count: usize,
+    pub example: String,
+}
+
+// When a change in a component's share is reported.
+#[derive(Debug, Clone, Copy)]
+pub struct DiffConfig {
+    pub min_share_change: f64, // absolute change in share, e.g. 0.05 for 5 percentage points
+    pub min_z_score: f64,      // and at least this unlikely to be noise
+}
+
+impl Default for DiffConfig {
+    fn default() -> Self {
+        DiffConfig {
+            min_share_change: 0.05,
+            min_z_score: 3.0,
+        }
+    }
+}
+
+// What changed between a baseline run ("before") and a later one ("after").
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatisticsDiff {
+    pub total_before: usize,
+    pub total_after: usize,
+    pub error_rate_before: f64,
+    pub error_rate_after: f64,
+    pub by_level: Vec<CountChange>,      // every level seen in either run, least severe first
+    pub by_component: Vec<CountChange>,  // every component seen in either run, largest change first
+    pub share_changes: Vec<ShareChange>, // largest change first
+    // None unless both runs grouped their messages into templates
+    pub new_templates: Option<Vec<TemplateChange>>, // most frequent first
+    pub gone_templates: Option<Vec<TemplateChange>>,
+}
+
+impl StatisticsDiff {
+    pub fn total_delta(&self) -> i64 {
+        self.total_after as i64 - self.total_before as i64
+    }
+}
+
+// Compares two runs. Template changes need both runs to have collected templates
+// (Statistics::with_templates).
+pub fn compare(before: &Statistics, after: &Statistics, config: &DiffConfig) -> StatisticsDiff {
+    let levels: BTreeSet<_> = before.entries_by_level.keys().chain(after.entries_by_level.keys()).collect();
+    let by_level = levels
+        .into_iter()
+        .map(|level| CountChange {
+            name: level.to_string(),
+            before: before.entries_by_level.get(level).copied().unwrap_or(0),
+            after: after.entries_by_level.get(level).copied().unwrap_or(0),
+        })
+        .collect();
+
+    let components: BTreeSet<&String> = before.entries_by_component.keys().chain(after.entries_by_component.keys()).collect();
+    let mut by_component: Vec<CountChange> = components
+        .iter()
+        .map(|component| CountChange {
+            name: component.to_string(),
+            before: before.entries_by_component.get(*component).copied().unwrap_or(0),
+            after: after.entries_by_component.get(*component).copied().unwrap_or(0),
+        })
+        .collect();
+    // Largest change first; alphabetical (the set order) on a tie
+    by_component.sort_by_key(|change| std::cmp::Reverse(change.delta().unsigned_abs()));
+
+    let mut share_changes: Vec<ShareChange> = by_component
+        .iter()
+        .filter_map(|change| share_change(change, before.total_entries, after.total_entries))
+        .filter(|change| (change.after - change.before).abs() >= config.min_share_change && change.z_score.abs() >= config.min_z_score)
+        .collect();
+    share_changes.sort_by(|a, b| (b.after - b.before).abs().total_cmp(&(a.after - a.before).abs()));
+
+    let (new_templates, gone_templates) = match (&before.templates, &after.templates) {
+        (Some(before), Some(after)) => {
+            let only_in = |these: &TemplateMiner, other: &TemplateMiner| {
+                let mut only: Vec<TemplateChange> = these
+                    .iter()
+                    .filter(|stats| other.get(&stats.template).is_none())
+                    .map(|stats| TemplateChange {
+                        template: stats.template.clone(),
+                        count: stats.count,
+                        example: stats.example.clone(),
+                    })
+                    .collect();
+                only.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.template.cmp(&b.template)));
+                only
+            };
+            (Some(only_in(after, before)), Some(only_in(before, after)))
+        }
+        _ => (None, None),
+    };
+
+    StatisticsDiff {
+        total_before: before.total_entries,
+        total_after: after.total_entries,
+        error_rate_before: before.error_rate,
+        error_rate_after: after.error_rate,
+        by_level,
+        by_component,
+        share_changes,
+        new_templates,
+        gone_templates,
+    }
+}
+
+// Shares of one component in both runs with a two-proportion z-test, or None
+// when either run is empty.
+fn share_change(change: &CountChange, total_before: usize, total_after: usize) -> Option<ShareChange> {
+    if total_before == 0 || total_after == 0 {
+        return None;
+    }
+    let (n1, n2) = (total_before as f64, total_after as f64);
+    let (p1, p2) = (change.before as f64 / n1, change.after as f64 / n2);
+    let pooled = (change.before + change.after) as f64 / (n1 + n2);
+    let se = (pooled * (1.0 - pooled) * (1.0 / n1 + 1.0 / n2)).sqrt();
+    // A component that is everything or nothing in both runs cannot shift
+    let z_score = if se > 0.0 { (p2 - p1) / se } else { 0.0 };
+    Some(ShareChange {
+        component: change.name.clone(),
+        before: p1,
+        after: p2,
+        z_score,
+    })
+}
@@ -0,0 +1,189 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::log_entry::{LogEntry, ParseError, ParseErrorKind};
+use crate::log_format::LogFormat;
+use crate::multiline::EntryStart;
+
+// Reads one line into `buf` without its line ending. Returns false at end of input.
+pub(crate) fn read_line<R: BufRead>(reader: &mut R, buf: &mut Vec<u8>) -> io::Result<bool> {
+    buf.clear();
+    if reader.read_until(b'\n', buf)? == 0 {
+        return Ok(false);
+    }
+    if buf.ends_with(b"\n") {
+        buf.pop();
+        if buf.ends_with(b"\r") {
+            buf.pop();
+        }
+    }
+    Ok(true)
+}
+
+// One parsed line (or multi-line group) of an input.
+#[derive(Debug)]
+pub enum Record {
+    Entry(LogEntry),
+    Error(ParseError),
+}
+
+// Turns raw lines into Records one at a time, joining continuation lines when a
+// multi-line rule is set. Only the current line and, in multi-line mode, the
+// entry being assembled are held in memory.
+pub struct LineParser {
+    path: PathBuf,
+    format: Arc<dyn LogFormat>,
+    multiline: Option<EntryStart>,
+    line_number: usize,
+    pending: Option<LogEntry>, // multi-line entry still collecting continuation lines
+    skip_continuation: bool,   // continuation lines of a header that failed to parse
+}
+
+impl LineParser {
+    pub fn new(path: &Path, format: Arc<dyn LogFormat>, multiline: Option<EntryStart>) -> Self {
+        LineParser {
+            path: path.to_path_buf(),
+            format,
+            multiline,
+            line_number: 0,
+            pending: None,
+            skip_continuation: false,
+        }
+    }
+
+    pub fn line_number(&self) -> usize {
+        self.line_number
+    }
+
+    // Feeds the next raw line (without line ending); completed records go to `out`.
+    // Lines with invalid UTF-8 are decoded lossily and recorded as a ParseError.
+    pub fn push_line(&mut self, buf: &[u8], out: &mut VecDeque<Record>) {
+        self.line_number += 1;
+        let line_number = self.line_number;
+        let path = self.path.as_path();
+
+        let line = match std::str::from_utf8(buf) {
+            Ok(line) => line,
+            Err(e) => {
+                let at = e.valid_up_to();
+                let content = String::from_utf8_lossy(buf);
+                out.push_back(Record::Error(
+                    ParseError::new(
+                        ParseErrorKind::InvalidEncoding,
+                        path,
+                        &content,
+                        line_number,
+                        format!("Invalid UTF-8 byte sequence at byte {}", at),
+                    )
+                    .with_span(at..at + char::REPLACEMENT_CHARACTER.len_utf8()),
+                ));
+                return;
+            }
+        };
+
+        let Some(entry_start) = &self.multiline else {
+            out.push_back(match self.format.parse_line(line, path, line_number) {
+                Ok(entry) => Record::Entry(entry),
+                Err(err) => Record::Error(err),
+            });
+            return;
+        };
+
+        match entry_start.parse_start(self.format.as_ref(), line, path, line_number) {
+            Some(result) => {
+                if let Some(entry) = self.pending.take() {
+                    out.push_back(Record::Entry(entry));
+                }
+                self.skip_continuation = result.is_err();
+                match result {
+                    Ok(entry) => self.pending = Some(entry),
+                    Err(err) => out.push_back(Record::Error(err)),
+                }
+            }
+            None => {
+                if line.trim().is_empty() || self.skip_continuation {
+                    return;
+                }
+                match &mut self.pending {
+                    Some(entry) => {
+                        entry.message.push('\n');
+                        entry.message.push_str(line.trim_end());
+                    }
+                    None => out.push_back(Record::Error(ParseError::new(
+                        ParseErrorKind::OrphanContinuation,
+                        path,
+                        line,
+                        line_number,
+                        "Continuation line without a preceding entry",
+                    ))),
+                }
+            }
+        }
+    }
+
+    // End of input: releases the entry still being assembled, if any.
+    pub fn finish(&mut self, out: &mut VecDeque<Record>) {
+        if let Some(entry) = self.pending.take() {
+            out.push_back(Record::Entry(entry));
+        }
+        self.skip_continuation = false;
+    }
+}
+
+// Iterator over the Records of one input. Lines already read ahead (e.g. for
+// format detection) are replayed before the rest of the reader.
+pub struct EntryReader<R> {
+    reader: R,
+    parser: LineParser,
+    replay: VecDeque<Vec<u8>>,
+    out: VecDeque<Record>,
+    buf: Vec<u8>,
+    done: bool,
+}
+
+impl<R: BufRead> EntryReader<R> {
+    pub fn new(reader: R, parser: LineParser, replay: Vec<Vec<u8>>) -> Self {
+        EntryReader {
+            reader,
+            parser,
+            replay: replay.into(),
+            out: VecDeque::new(),
+            buf: Vec::new(),
+            done: false,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for EntryReader<R> {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(record) = self.out.pop_front() {
+                return Some(Ok(record));
+            }
+            if self.done {
+                return None;
+            }
+
+            if let Some(line) = self.replay.pop_front() {
+                self.parser.push_line(&line, &mut self.out);
+                continue;
+            }
+
+            match read_line(&mut self.reader, &mut self.buf) {
+                Ok(true) => self.parser.push_line(&self.buf, &mut self.out),
+                Ok(false) => {
+                    self.parser.finish(&mut self.out);
+                    self.done = true;
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
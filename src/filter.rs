@@ -0,0 +1,83 @@
+use regex::Regex;
+
+use crate::date_time::DateTime;
+use crate::log_entry::LogEntry;
+use crate::log_level::LogLevel;
+
+#[derive(Debug, Clone)]
+pub enum MessageMatch {
+    Contains(String),
+    Regex(Regex),
+}
+
+impl MessageMatch {
+    pub fn is_match(&self, message: &str) -> bool {
+        match self {
+            MessageMatch::Contains(text) => message.contains(text.as_str()),
+            MessageMatch::Regex(re) => re.is_match(message),
+        }
+    }
+}
+
+// Which entries the analyzer keeps. Every condition that is set must hold; unset
+// conditions (None or empty lists) let everything through, so the default filter
+// keeps all entries.
+#[derive(Debug, Clone, Default)]
+pub struct EntryFilter {
+    pub min_level: Option<LogLevel>,
+    pub max_level: Option<LogLevel>,
+    pub components: Vec<String>, // keep only these components
+    pub exclude_components: Vec<String>,
+    pub since: Option<DateTime>, // inclusive
+    pub until: Option<DateTime>, // exclusive
+    pub messages: Vec<MessageMatch>,
+}
+
+impl EntryFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_min_level(mut self, level: LogLevel) -> Self {
+        self.min_level = Some(level);
+        self
+    }
+
+    pub fn with_max_level(mut self, level: LogLevel) -> Self {
+        self.max_level = Some(level);
+        self
+    }
+
+    pub fn with_component(mut self, component: &str) -> Self {
+        self.components.push(component.to_string());
+        self
+    }
+
+    pub fn without_component(mut self, component: &str) -> Self {
+        self.exclude_components.push(component.to_string());
+        self
+    }
+
+    // Entries with since <= timestamp < until.
+    pub fn with_time_range(mut self, since: Option<DateTime>, until: Option<DateTime>) -> Self {
+        self.since = since;
+        self.until = until;
+        self
+    }
+
+    pub fn with_message(mut self, message: MessageMatch) -> Self {
+        self.messages.push(message);
+        self
+    }
+
+    pub fn matches(&self, entry: &LogEntry) -> bool {
+        // Cheapest checks first; the message patterns go last
+        self.min_level.is_none_or(|min| entry.level >= min)
+            && self.max_level.is_none_or(|max| entry.level <= max)
+            && (self.components.is_empty() || self.components.contains(&entry.component))
+            && !self.exclude_components.contains(&entry.component)
+            && self.since.as_ref().is_none_or(|since| entry.timestamp >= *since)
+            && self.until.as_ref().is_none_or(|until| entry.timestamp < *until)
+            && self.messages.iter().all(|m| m.is_match(&entry.message))
+    }
+}
@@ -0,0 +1,149 @@
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+// How long an unterminated last line has to stay unchanged before it is taken as
+// complete. Much longer than a poll interval, so a slow writer's line is not split.
+pub const PARTIAL_LINE_GRACE: Duration = Duration::from_secs(30);
+
+// What a FileTail found on one poll.
+#[derive(Debug, PartialEq)]
+pub enum TailEvent {
+    Line(Vec<u8>), // a complete line, without its line ending
+    Reopened,      // the file was truncated or replaced; later lines come from the start of the new content
+}
+
+// Identity of the file behind a path, to notice when it is rotated away.
+#[cfg(unix)]
+fn file_id(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+// Follows one file by name, like `tail -F`: appended lines are returned as they
+// arrive, and the file is reopened when it is truncated or replaced (rotated).
+// A path that does not exist yet is picked up once it is created.
+pub struct FileTail {
+    path: PathBuf,
+    reader: Option<BufReader<File>>,
+    id: Option<(u64, u64)>,
+    position: u64,
+    partial: Vec<u8>, // last line, still waiting for its newline
+    partial_grew: Instant,
+    grace: Duration,
+}
+
+impl FileTail {
+    pub fn new(path: &Path) -> Self {
+        FileTail {
+            path: path.to_path_buf(),
+            reader: None,
+            id: None,
+            position: 0,
+            partial: Vec::new(),
+            partial_grew: Instant::now(),
+            grace: PARTIAL_LINE_GRACE,
+        }
+    }
+
+    // Take an unterminated last line as complete once it has not grown for `grace`.
+    pub fn with_partial_line_grace(mut self, grace: Duration) -> Self {
+        self.grace = grace;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Collects everything written since the last poll into `out`.
+    pub fn poll(&mut self, out: &mut Vec<TailEvent>) -> io::Result<()> {
+        if self.reader.is_none() && !self.open()? {
+            return Ok(());
+        }
+        // An unterminated last line that stopped growing for the grace period is
+        // taken as complete, so a file without a final newline is fully read
+        if self.read_available(out)? == 0 && !self.partial.is_empty() && self.partial_grew.elapsed() >= self.grace {
+            out.push(TailEvent::Line(std::mem::take(&mut self.partial)));
+        }
+
+        let current = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            // Moved away and not recreated yet: keep the old handle until it is
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        if file_id(&current) != self.id {
+            // Rotated: the old file is finished, so its unterminated last line is complete
+            if !self.partial.is_empty() {
+                out.push(TailEvent::Line(std::mem::take(&mut self.partial)));
+            }
+            if self.open()? {
+                out.push(TailEvent::Reopened);
+                self.read_available(out)?;
+            }
+        } else if current.len() < self.position {
+            // Truncated in place (copytruncate): the old content is finished too
+            if let Some(reader) = &mut self.reader {
+                reader.seek(SeekFrom::Start(0))?;
+            }
+            self.position = 0;
+            if !self.partial.is_empty() {
+                out.push(TailEvent::Line(std::mem::take(&mut self.partial)));
+            }
+            out.push(TailEvent::Reopened);
+            self.read_available(out)?;
+        }
+        Ok(())
+    }
+
+    // Opens the path from its start. Returns false if it does not exist.
+    fn open(&mut self) -> io::Result<bool> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        self.id = file_id(&file.metadata()?);
+        self.reader = Some(BufReader::new(file));
+        self.position = 0;
+        self.partial.clear();
+        Ok(true)
+    }
+
+    // Reads the complete lines currently available from the open handle.
+    // Returns the number of bytes read.
+    fn read_available(&mut self, out: &mut Vec<TailEvent>) -> io::Result<u64> {
+        let Some(reader) = &mut self.reader else {
+            return Ok(0);
+        };
+        let mut total = 0;
+        loop {
+            let read = reader.read_until(b'\n', &mut self.partial)?;
+            if read == 0 {
+                if total > 0 && !self.partial.is_empty() {
+                    self.partial_grew = Instant::now();
+                }
+                return Ok(total);
+            }
+            total += read as u64;
+            self.position += read as u64;
+
+            if self.partial.ends_with(b"\n") {
+                let mut line = std::mem::take(&mut self.partial);
+                line.pop();
+                if line.ends_with(b"\r") {
+                    line.pop();
+                }
+                out.push(TailEvent::Line(line));
+            }
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use crate::log_format::{builtin_formats, LogFormat};
+use crate::multiline::EntryStart;
+
+// Format picked for one input file and the share of sampled lines it parsed.
+#[derive(Debug, Clone, Serialize)]
+pub struct DetectedFormat {
+    pub path: PathBuf,
+    pub format: String,
+    pub confidence: f64,
+}
+
+// Chooses a LogFormat for a file by trying every candidate on its first lines.
+#[derive(Clone)]
+pub struct FormatDetector {
+    candidates: Vec<Arc<dyn LogFormat>>,
+    sample_lines: usize,
+    min_confidence: f64,
+}
+
+impl Default for FormatDetector {
+    fn default() -> Self {
+        Self::new(builtin_formats())
+    }
+}
+
+impl FormatDetector {
+    pub fn new(candidates: Vec<Arc<dyn LogFormat>>) -> Self {
+        Self {
+            candidates,
+            sample_lines: 50,
+            min_confidence: 0.6,
+        }
+    }
+
+    // Number of lines read from the start of each file before choosing.
+    pub fn with_sample_lines(mut self, sample_lines: usize) -> Self {
+        self.sample_lines = sample_lines.max(1);
+        self
+    }
+
+    // Files whose best candidate parses a smaller share of the sample are rejected.
+    pub fn with_min_confidence(mut self, min_confidence: f64) -> Self {
+        self.min_confidence = min_confidence;
+        self
+    }
+
+    pub fn sample_lines(&self) -> usize {
+        self.sample_lines
+    }
+
+    pub fn min_confidence(&self) -> f64 {
+        self.min_confidence
+    }
+
+    // Scores every candidate by parse success rate over the non-empty sample lines.
+    // Ties go to the candidate listed first. None if the sample has no content.
+    pub fn detect(&self, sample: &[String], path: &Path) -> Option<(Arc<dyn LogFormat>, f64)> {
+        self.best_candidate(sample, path, None)
+    }
+
+    // Like detect, for input with multi-line entries: only the lines that start an
+    // entry are scored, and the continuation lines after them are skipped.
+    pub fn detect_multiline(
+        &self,
+        sample: &[String],
+        path: &Path,
+        entry_start: &EntryStart,
+    ) -> Option<(Arc<dyn LogFormat>, f64)> {
+        self.best_candidate(sample, path, Some(entry_start))
+    }
+
+    fn best_candidate(
+        &self,
+        sample: &[String],
+        path: &Path,
+        entry_start: Option<&EntryStart>,
+    ) -> Option<(Arc<dyn LogFormat>, f64)> {
+        let lines: Vec<(usize, &str)> = sample
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| (i + 1, line.as_str()))
+            .collect();
+
+        if lines.is_empty() {
+            return None;
+        }
+
+        let mut best: Option<(Arc<dyn LogFormat>, f64)> = None;
+        for candidate in &self.candidates {
+            let (mut scored, mut parsed) = (0usize, 0usize);
+            let mut in_entry = false;
+            for (n, line) in &lines {
+                let ok = match entry_start {
+                    None => candidate.parse_line(line, path, *n).is_ok(),
+                    Some(start) => match start.parse_start(candidate.as_ref(), line, path, *n) {
+                        Some(result) => {
+                            in_entry = true;
+                            result.is_ok()
+                        }
+                        None if in_entry => continue,
+                        // A continuation line before any entry is an error either way
+                        None => false,
+                    },
+                };
+                scored += 1;
+                parsed += usize::from(ok);
+            }
+            let score = parsed as f64 / scored as f64;
+
+            if best.as_ref().is_none_or(|(_, s)| score > *s) {
+                best = Some((candidate.clone(), score));
+            }
+        }
+        best
+    }
+}
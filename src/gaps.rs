@@ -0,0 +1,261 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::date_time::DateTime;
+use crate::log_entry::LogEntry;
+use crate::snapshot::sorted_map;
+
+// Silences kept per component; the longest ones, so the gaps worth reporting.
+pub const MAX_SILENCES: usize = 32;
+
+// When each component logged, summarized to find when it went quiet: per component
+// the first and last second it logged, a histogram of the intervals between its
+// entries and its MAX_SILENCES longest silences. Memory stays bounded however long
+// the input is. Activity merges exactly when the inputs cover separate stretches of
+// time (rotated files, one day after another); where they overlap, a silence of one
+// only survives where the other was silent too.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ComponentActivity {
+    #[serde(serialize_with = "sorted_map")]
+    components: HashMap<String, Track>,
+}
+
+// One component's activity, in Unix seconds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Track {
+    first: i64,
+    last: i64,
+    intervals: BTreeMap<u32, u64>, // count per interval_bucket
+    silences: Vec<(i64, i64)>,     // (last second before, next second), sorted by start
+}
+
+impl ComponentActivity {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, entry: &LogEntry) {
+        let point = Track::point(entry.timestamp.unix_seconds());
+        match self.components.get_mut(&entry.component) {
+            Some(track) => track.merge(&point),
+            None => {
+                self.components.insert(entry.component.clone(), point);
+            }
+        }
+    }
+
+    pub fn merge(&mut self, other: &ComponentActivity) {
+        for (component, theirs) in &other.components {
+            match self.components.get_mut(component) {
+                Some(track) => track.merge(theirs),
+                None => {
+                    self.components.insert(component.clone(), theirs.clone());
+                }
+            }
+        }
+    }
+}
+
+impl Track {
+    fn point(second: i64) -> Self {
+        Track { first: second, last: second, intervals: BTreeMap::new(), silences: Vec::new() }
+    }
+
+    fn merge(&mut self, other: &Track) {
+        for (&bucket, &count) in &other.intervals {
+            *self.intervals.entry(bucket).or_insert(0) += count;
+        }
+
+        if other.first >= self.last || other.last <= self.first {
+            // One after the other: the silence between them is known exactly
+            let (start, end) = if other.first >= self.last { (self.last, other.first) } else { (other.last, self.first) };
+            for &(start, end) in &other.silences {
+                self.record_silence(start, end);
+            }
+            if end > start {
+                *self.intervals.entry(interval_bucket(end - start)).or_insert(0) += 1;
+                self.record_silence(start, end);
+            }
+        } else {
+            // Overlapping: silent only where both were. The interval histogram is
+            // only approximate from here on.
+            let mut silences = intersect(&self.silent_ranges(), &other.silent_ranges());
+            silences.retain(|&(start, end)| start != i64::MIN && end != i64::MAX);
+            if silences.len() > MAX_SILENCES {
+                silences.sort_by_key(|&(start, end)| (Reverse(end - start), start));
+                silences.truncate(MAX_SILENCES);
+                silences.sort_unstable();
+            }
+            self.silences = silences;
+        }
+
+        self.first = self.first.min(other.first);
+        self.last = self.last.max(other.last);
+    }
+
+    // Keeps the silence if it is among the MAX_SILENCES longest (the earliest on a tie).
+    fn record_silence(&mut self, start: i64, end: i64) {
+        let at = self.silences.partition_point(|&(s, _)| s < start);
+        self.silences.insert(at, (start, end));
+        if self.silences.len() > MAX_SILENCES
+            && let Some((shortest, _)) =
+                self.silences.iter().enumerate().min_by_key(|&(_, &(s, e))| (e - s, Reverse(s)))
+        {
+            self.silences.remove(shortest);
+        }
+    }
+
+    // Where the component logged nothing, as sorted open ranges, unbounded at both ends.
+    fn silent_ranges(&self) -> Vec<(i64, i64)> {
+        let mut ranges = Vec::with_capacity(self.silences.len() + 2);
+        ranges.push((i64::MIN, self.first));
+        ranges.extend_from_slice(&self.silences);
+        ranges.push((self.last, i64::MAX));
+        ranges
+    }
+
+    // Median interval between entries, to the precision of interval_bucket.
+    fn usual_interval(&self) -> Option<i64> {
+        let total: u64 = self.intervals.values().sum();
+        let mut seen = 0;
+        for (&bucket, &count) in &self.intervals {
+            seen += count;
+            if seen > total / 2 {
+                return Some(bucket_start(bucket));
+            }
+        }
+        None
+    }
+}
+
+// Overlaps of two lists of sorted, disjoint open ranges.
+fn intersect(a: &[(i64, i64)], b: &[(i64, i64)]) -> Vec<(i64, i64)> {
+    let (mut i, mut j) = (0, 0);
+    let mut out = Vec::new();
+    while i < a.len() && j < b.len() {
+        let start = a[i].0.max(b[j].0);
+        let end = a[i].1.min(b[j].1);
+        if start < end {
+            out.push((start, end));
+        }
+        if a[i].1 < b[j].1 {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    out
+}
+
+// Histogram bucket of an interval: exact below 64 seconds, then eight buckets
+// per doubling, so a bucket is within 12.5% of the intervals in it.
+fn interval_bucket(seconds: i64) -> u32 {
+    let seconds = seconds as u64;
+    if seconds < 64 {
+        return seconds as u32;
+    }
+    let exp = 63 - seconds.leading_zeros();
+    let sub = (seconds >> (exp - 3)) & 7;
+    64 + (exp - 6) * 8 + sub as u32
+}
+
+fn bucket_start(bucket: u32) -> i64 {
+    if bucket < 64 {
+        return bucket as i64;
+    }
+    let exp = (bucket - 64) / 8 + 6;
+    let sub = (bucket - 64) % 8;
+    (8 + sub as i64) << (exp - 3)
+}
+
+// When a silence counts as a gap.
+#[derive(Debug, Clone, Copy)]
+pub struct GapConfig {
+    pub threshold: Duration,  // any silence at least this long
+    pub factor: f64,          // or this many times the component's usual interval
+    pub min_intervals: usize, // intervals needed before a component's usual interval is trusted
+}
+
+impl Default for GapConfig {
+    fn default() -> Self {
+        GapConfig {
+            threshold: Duration::from_secs(300),
+            factor: 10.0,
+            min_intervals: 10,
+        }
+    }
+}
+
+// A stretch of time in which a component logged nothing.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Gap {
+    pub component: String,
+    pub start: DateTime, // last entry before the silence (to the second)
+    pub end: DateTime,   // next entry, or the end of the input if the component never logged again
+    #[serde(rename = "duration_secs", serialize_with = "whole_seconds")]
+    pub duration: Duration,
+    #[serde(rename = "usual_interval_secs", serialize_with = "optional_whole_seconds")]
+    pub usual: Option<Duration>, // median interval between the component's entries
+    pub trailing: bool,          // the component was still silent when the input ended
+}
+
+// Gaps are found to the second, so their durations are serialized as whole seconds.
+fn whole_seconds<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_secs())
+}
+
+fn optional_whole_seconds<S: Serializer>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error> {
+    duration.map(|duration| duration.as_secs()).serialize(serializer)
+}
+
+// Silences per component that are longer than `config.threshold`, or longer
+// than `config.factor` times the component's median interval between entries.
+// `input_end` (the last entry of the whole input) also reveals components that
+// stopped logging for good. Gaps are sorted by component, then start.
+pub fn find_gaps(activity: &ComponentActivity, config: &GapConfig, input_end: Option<&DateTime>) -> Vec<Gap> {
+    let input_end = input_end.map(DateTime::unix_seconds);
+    let threshold = config.threshold.as_secs() as i64;
+
+    // Sorted by component for a stable report
+    let components: BTreeMap<&String, &Track> = activity.components.iter().collect();
+    let mut gaps = Vec::new();
+    for (component, track) in components {
+        let intervals: u64 = track.intervals.values().sum();
+        let usual = (intervals >= config.min_intervals.max(1) as u64).then(|| track.usual_interval()).flatten();
+        let is_gap = |length: i64| {
+            length > 0 && (length >= threshold || usual.is_some_and(|usual| length as f64 >= config.factor * usual as f64))
+        };
+
+        let mut found = |start: i64, end: i64, trailing: bool| {
+            if !is_gap(end - start) {
+                return;
+            }
+            // Hand-built timestamps outside 1970..=9999 cannot be reported
+            if let (Some(start_time), Some(end_time)) = (at(start), at(end)) {
+                gaps.push(Gap {
+                    component: component.clone(),
+                    start: start_time,
+                    end: end_time,
+                    duration: Duration::from_secs((end - start) as u64),
+                    usual: usual.map(|usual| Duration::from_secs(usual as u64)),
+                    trailing,
+                });
+            }
+        };
+        for &(start, end) in &track.silences {
+            found(start, end, false);
+        }
+        if let Some(input_end) = input_end {
+            found(track.last, input_end, true);
+        }
+    }
+    gaps
+}
+
+fn at(unix_seconds: i64) -> Option<DateTime> {
+    DateTime::from_unix_seconds(unix_seconds).ok()
+}
@@ -1,6 +1,23 @@
 pub mod date_time;
 pub mod log_level;
 pub mod log_entry;
+pub mod diagnostics;
+pub mod log_format;
+pub mod format_detector;
+pub mod multiline;
+pub mod entry_reader;
+pub mod rotation;
+pub mod follow;
+pub mod filter;
+pub mod query;
+pub mod timeseries;
+pub mod templates;
+pub mod anomaly;
+pub mod gaps;
+pub mod diff;
+pub mod snapshot;
 pub mod statistics_aggregator;
+pub mod decompress;
+pub mod walk;
 pub mod log_analyzer;
 pub mod report;
\ No newline at end of file
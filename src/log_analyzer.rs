@@ -1,33 +1,132 @@
-use std::fs::{self, File};
-use std::io::{self, Read};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, BinaryHeap, VecDeque};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
 
+use thiserror::Error;
+
+use crate::decompress::decompressed;
+use crate::entry_reader::{read_line, EntryReader, LineParser, Record};
+use crate::filter::EntryFilter;
+use crate::follow::{FileTail, TailEvent, PARTIAL_LINE_GRACE};
+use crate::format_detector::{DetectedFormat, FormatDetector};
 use crate::log_entry::{LogEntry, ParseError};
+use crate::log_format::{BracketedFormat, LogFormat};
+use crate::multiline::EntryStart;
+use crate::rotation::sort_by_rotation;
+use crate::walk::{walk_directory, SkippedFile, WalkOptions};
 use crate::statistics_aggregator::Statistics;
-use crate::log_entry::parse_log_line;
+use crate::timeseries::BucketWidth;
 
-#[derive(Debug)]
+#[derive(Debug, Error)]
 pub enum AnalyzerError {
+    #[error("failed to read {}: {source}", .path.display())]
     IoError { path: PathBuf, source: io::Error },
+    #[error("no log files found in {}", .path.display())]
     NoFilesFound { path: PathBuf },
+    #[error("invalid path {}: {reason}", .path.display())]
     InvalidPath { path: PathBuf, reason: String },
+    #[error(
+        "could not detect the log format of {}: best guess '{best_guess}' parsed only {:.1}% of sampled lines",
+        .path.display(),
+        .confidence * 100.0
+    )]
+    UnknownFormat { path: PathBuf, best_guess: String, confidence: f64 },
+    #[error("invalid file pattern: {reason}")]
+    InvalidPattern { reason: String },
 }
 
-pub struct LogAnalyzer {
-    entries: Vec<LogEntry>,
-    errors: Vec<ParseError>,
+// Inputs process_files_merged holds open at once, one handle (and decompressor)
+// each. Kept below the common soft limit of 256 open files per process; more
+// files are merged in passes through temporary files.
+pub const MAX_OPEN_MERGE_INPUTS: usize = 200;
+
+// Path argument that stands for standard input, and the source_file label its entries get.
+pub const STDIN_PATH: &str = "-";
+pub const STDIN_LABEL: &str = "<stdin>";
+
+fn input_label(path: &Path) -> &Path {
+    if path == Path::new(STDIN_PATH) { Path::new(STDIN_LABEL) } else { path }
 }
 
-impl LogAnalyzer {
-    pub fn new() -> Self {
-        Self {
-            entries: Vec::new(),
-            errors: Vec::new(),
+// Regular files and, on unix, named pipes can be read as inputs.
+fn is_readable_input(path: &Path) -> bool {
+    let Ok(metadata) = path.metadata() else {
+        return false;
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        if metadata.file_type().is_fifo() {
+            return true;
         }
     }
+    metadata.is_file()
+}
+
+// An input of the k-way merge: a log file, or a run of entries that an earlier
+// pass merged into a temporary file, one JSON object per line.
+enum MergeInput {
+    File(PathBuf),
+    Run(PathBuf),
+}
+
+// A MergeInput once opened.
+enum MergeStream {
+    File(Box<EntryReader<Box<dyn BufRead + Send>>>, PathBuf),
+    Run(BufReader<File>, PathBuf),
+}
+
+// Head of one input's stream in the k-way merge, ordered by timestamp then input.
+struct MergeItem {
+    entry: LogEntry,
+    source: usize,
+}
+
+impl PartialEq for MergeItem {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for MergeItem {}
+
+impl PartialOrd for MergeItem {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for MergeItem {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.entry.timestamp.cmp(&other.entry.timestamp).then(self.source.cmp(&other.source))
+    }
+}
+
+// How inputs are turned into records. Kept apart from the results so that
+// worker threads can share it.
+#[derive(Clone)]
+struct ParseConfig {
+    format: Arc<dyn LogFormat>,
+    detector: Option<FormatDetector>,
+    multiline: Option<EntryStart>,
+}
+
+// An input's entry stream and the format detected for it, if detection is on.
+type Opened<R> = (EntryReader<R>, Option<DetectedFormat>);
+
+impl ParseConfig {
+    // Opens a file (or stdin for `-`), decompressing it if needed, and prepares its entry stream.
+    fn open_file(&self, path: &Path) -> Result<Opened<Box<dyn BufRead + Send>>, AnalyzerError> {
+        if path == Path::new(STDIN_PATH) {
+            return self.open_reader(BufReader::new(io::stdin()), Path::new(STDIN_LABEL));
+        }
 
-    // Read one log file.
-    pub fn process_file(&mut self, path: &Path) -> Result<usize, AnalyzerError> {
         if !path.exists() {
             return Err(AnalyzerError::InvalidPath {
                 path: path.to_path_buf(),
@@ -35,43 +134,454 @@ impl LogAnalyzer {
             });
         }
 
-        if !path.is_file() {
+        if !is_readable_input(path) {
             return Err(AnalyzerError::InvalidPath {
                 path: path.to_path_buf(),
                 reason: "Expected a file path".to_string(),
             });
         }
 
-        let mut file = File::open(path).map_err(|e| AnalyzerError::IoError {
+        let file = File::open(path).map_err(|e| AnalyzerError::IoError {
             path: path.to_path_buf(),
             source: e,
         })?;
 
-        let mut contents = String::new();
-        file.read_to_string(&mut contents).map_err(|e| AnalyzerError::IoError {
-            path: path.to_path_buf(),
+        self.open_reader(BufReader::new(file), path)
+    }
+
+    fn open_reader<R: BufRead + Send + 'static>(
+        &self,
+        reader: R,
+        label: &Path,
+    ) -> Result<Opened<Box<dyn BufRead + Send>>, AnalyzerError> {
+        let reader = decompressed(reader, label).map_err(|e| AnalyzerError::IoError {
+            path: label.to_path_buf(),
             source: e,
         })?;
 
-        let mut success_count = 0usize;
+        self.open_stream(reader, label)
+    }
 
-        for (i, line) in contents.lines().enumerate() {
-            match parse_log_line(line, path, i+1) {
-                Ok(entry) => {
-                    self.entries.push(entry);
-                    success_count += 1;
-                }
-                Err(err) => {
-                    self.errors.push(err);
+    // Wraps a reader in an entry stream. With format detection enabled, the first
+    // lines are read ahead to choose the format and then replayed.
+    fn open_stream<R: BufRead>(&self, mut reader: R, path: &Path) -> Result<Opened<R>, AnalyzerError> {
+        let mut sample: Vec<Vec<u8>> = Vec::new();
+
+        if let Some(sample_lines) = self.detector.as_ref().map(FormatDetector::sample_lines) {
+            let mut buf = Vec::new();
+            while sample.len() < sample_lines
+                && read_line(&mut reader, &mut buf).map_err(|e| AnalyzerError::IoError {
+                    path: path.to_path_buf(),
+                    source: e,
+                })?
+            {
+                sample.push(std::mem::take(&mut buf));
+            }
+        }
+
+        let (parser, detected) = self.new_parser(&sample, path)?;
+        Ok((EntryReader::new(reader, parser, sample), detected))
+    }
+
+    // Parser for one input, with the format detected from `sample` when detection is enabled.
+    fn new_parser(&self, sample: &[Vec<u8>], path: &Path) -> Result<(LineParser, Option<DetectedFormat>), AnalyzerError> {
+        let (format, detected) = self.detect_format(sample, path)?;
+        Ok((LineParser::new(path, format, self.multiline.clone()), detected))
+    }
+
+    // Pick the format for one file from its sampled lines.
+    fn detect_format(
+        &self,
+        sample: &[Vec<u8>],
+        path: &Path,
+    ) -> Result<(Arc<dyn LogFormat>, Option<DetectedFormat>), AnalyzerError> {
+        let Some(detector) = &self.detector else {
+            return Ok((self.format.clone(), None));
+        };
+
+        let lines: Vec<String> = sample
+            .iter()
+            .map(|line| String::from_utf8_lossy(line).into_owned())
+            .collect();
+
+        let best = match &self.multiline {
+            Some(entry_start) => detector.detect_multiline(&lines, path, entry_start),
+            None => detector.detect(&lines, path),
+        };
+        let Some((format, confidence)) = best else {
+            // Nothing but blank lines to go on
+            return Ok((self.format.clone(), None));
+        };
+
+        if confidence < detector.min_confidence() {
+            return Err(AnalyzerError::UnknownFormat {
+                path: path.to_path_buf(),
+                best_guess: format.name().to_string(),
+                confidence,
+            });
+        }
+
+        let detected = DetectedFormat {
+            path: path.to_path_buf(),
+            format: format.name().to_string(),
+            confidence,
+        };
+        Ok((format, Some(detected)))
+    }
+}
+
+// What is kept of the parsed input besides the statistics.
+#[derive(Debug, Clone, Copy)]
+struct Retention {
+    keep_entries: bool,
+    max_parse_errors: Option<usize>,
+}
+
+impl Retention {
+    fn keeps_error(&self, kept: usize) -> bool {
+        self.max_parse_errors.is_none_or(|max| kept < max)
+    }
+}
+
+// Everything a worker read from one file, folded into the analyzer in file order.
+// `error` is set if reading stopped early; the rest then holds what came before it.
+struct FileOutput {
+    detected: Option<DetectedFormat>,
+    records: Vec<Record>,
+    // Entries already folded by the worker when entries are not kept
+    stats: Option<Statistics>,
+    filtered: usize,       // entries rejected by the filter
+    dropped_errors: usize, // parse errors beyond the cap
+    error: Option<AnalyzerError>,
+}
+
+// `empty_stats` carries the statistics options (e.g. the timeline width).
+fn read_file(
+    config: &ParseConfig,
+    filter: &EntryFilter,
+    retention: Retention,
+    empty_stats: &Statistics,
+    path: &Path,
+) -> FileOutput {
+    let mut output = FileOutput {
+        detected: None,
+        records: Vec::new(),
+        stats: (!retention.keep_entries).then(|| empty_stats.clone()),
+        filtered: 0,
+        dropped_errors: 0,
+        error: None,
+    };
+
+    let stream = match config.open_file(path) {
+        Ok((stream, detected)) => {
+            output.detected = detected;
+            stream
+        }
+        Err(e) => {
+            output.error = Some(e);
+            return output;
+        }
+    };
+
+    let mut kept_errors = 0;
+    for record in stream {
+        match record {
+            Ok(Record::Entry(entry)) if !filter.matches(&entry) => output.filtered += 1,
+            Ok(Record::Entry(entry)) => match &mut output.stats {
+                Some(stats) => stats.add(&entry),
+                None => output.records.push(Record::Entry(entry)),
+            },
+            Ok(Record::Error(err)) => {
+                // The first errors of each file include the first errors overall
+                if retention.keeps_error(kept_errors) {
+                    kept_errors += 1;
+                    output.records.push(Record::Error(err));
+                } else {
+                    output.dropped_errors += 1;
                 }
             }
+            Err(e) => {
+                output.error = Some(AnalyzerError::IoError {
+                    path: input_label(path).to_path_buf(),
+                    source: e,
+                });
+                break;
+            }
         }
+    }
+    output
+}
 
-        Ok(success_count)
+// A file in follow mode. The parser is created once the detector's sample
+// lines have arrived, or the partial-line grace period has passed since the first.
+struct Followed {
+    tail: FileTail,
+    parser: Option<LineParser>,
+    sample: Vec<Vec<u8>>, // lines held back for format detection
+    sampling_since: Instant,
+    failures: u32, // polls failed in a row
+}
+
+// A followed file whose poll fails this many times in a row is given up on.
+pub const MAX_FOLLOW_FAILURES: u32 = 10;
+
+// What one poll of the followed files found.
+#[derive(Debug, Default)]
+pub struct PollOutcome {
+    pub entries: usize,
+    // One error per file that could not be read; the other files were still polled
+    pub errors: Vec<AnalyzerError>,
+    // Files no longer followed after MAX_FOLLOW_FAILURES failed polls in a row
+    pub stopped: Vec<PathBuf>,
+}
+
+pub struct LogAnalyzer {
+    entries: Vec<LogEntry>,
+    stats: Statistics,
+    errors: Vec<ParseError>,
+    error_total: usize,
+    config: ParseConfig,
+    retention: Retention,
+    filter: EntryFilter,
+    filtered: usize,
+    detected_formats: Vec<DetectedFormat>,
+    walk: WalkOptions,
+    merge: bool,
+    workers: usize,
+    skipped_files: Vec<SkippedFile>,
+    followed: Vec<Followed>,
+    partial_line_grace: Duration,
+}
+
+impl Default for LogAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LogAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            stats: Statistics::new(),
+            errors: Vec::new(),
+            error_total: 0,
+            config: ParseConfig {
+                format: Arc::new(BracketedFormat),
+                detector: None,
+                multiline: None,
+            },
+            retention: Retention { keep_entries: true, max_parse_errors: None },
+            filter: EntryFilter::default(),
+            filtered: 0,
+            detected_formats: Vec::new(),
+            walk: WalkOptions::default(),
+            merge: false,
+            workers: 1,
+            skipped_files: Vec::new(),
+            followed: Vec::new(),
+            partial_line_grace: PARTIAL_LINE_GRACE,
+        }
+    }
+
+    // Parse every line with `format` instead of the default bracketed layout.
+    pub fn with_format(mut self, format: Arc<dyn LogFormat>) -> Self {
+        self.config.format = format;
+        self.config.detector = None;
+        self
     }
 
-    // Read all log files in a directory 
+    // Join continuation lines (e.g. stack traces) onto the entry before them.
+    // `entry_start` decides which lines begin a new entry.
+    pub fn with_multiline(mut self, entry_start: EntryStart) -> Self {
+        self.config.multiline = Some(entry_start);
+        self
+    }
+
+    // Controls recursion, glob filters and symlink handling in process_directory.
+    pub fn with_walk_options(mut self, walk: WalkOptions) -> Self {
+        self.walk = walk;
+        self
+    }
+
+    // Merge the files of a directory into one timestamp-ordered stream, reading
+    // rotated files (app.log.2, app.log.1, app.log) oldest first.
+    pub fn with_chronological_merge(mut self, merge: bool) -> Self {
+        self.merge = merge;
+        self
+    }
+
+    // Choose the format of each file from its first lines instead of using a fixed one.
+    pub fn with_format_detection(mut self, detector: FormatDetector) -> Self {
+        self.config.detector = Some(detector);
+        self
+    }
+
+    // Keep only the entries `filter` matches. Entries it rejects are not stored or
+    // counted in the statistics, only tallied in filtered_count.
+    pub fn with_filter(mut self, filter: EntryFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    // Also count entries over absolute time in buckets of `width`; see Statistics::timeline.
+    pub fn with_timeline(mut self, width: BucketWidth) -> Self {
+        self.stats = self.stats.with_timeline(width);
+        self
+    }
+
+    // Also group entries by message template; see Statistics::templates.
+    pub fn with_templates(mut self) -> Self {
+        self.stats = self.stats.with_templates();
+        self
+    }
+
+    // Also record when each component logged, for gap detection; see Statistics::activity.
+    pub fn with_activity(mut self) -> Self {
+        self.stats = self.stats.with_activity();
+        self
+    }
+
+    // Aggregation only: entries are folded into the statistics as they are parsed
+    // and then dropped, so entries() stays empty and memory does not grow with the input.
+    pub fn with_statistics_only(mut self, statistics_only: bool) -> Self {
+        self.retention.keep_entries = !statistics_only;
+        self
+    }
+
+    // In follow mode, take an unterminated last line as complete once it has not
+    // grown for `grace` (default PARTIAL_LINE_GRACE). Applies to files followed afterwards.
+    pub fn with_partial_line_grace(mut self, grace: Duration) -> Self {
+        self.partial_line_grace = grace;
+        self
+    }
+
+    // Keep at most `max` parse errors as a sample; the rest are only counted
+    // (see parse_error_count).
+    pub fn with_max_parse_errors(mut self, max: usize) -> Self {
+        self.retention.max_parse_errors = Some(max);
+        self
+    }
+
+    // Parse up to `workers` files at once in process_directory. Entries, parse
+    // errors and statistics come out exactly as with a single worker.
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    // Read one log file; gzip, zstd and bzip2 files are decompressed on the fly.
+    // `-` reads standard input, and named pipes are read like files.
+    pub fn process_file(&mut self, path: &Path) -> Result<usize, AnalyzerError> {
+        let (stream, detected) = self.config.open_file(path)?;
+        self.detected_formats.extend(detected);
+        self.consume(stream, input_label(path))
+    }
+
+    // Read log lines from any reader, e.g. a pipe or a socket. `label` is used as
+    // the source_file of its entries and in errors.
+    pub fn process_reader<R: BufRead + Send + 'static>(&mut self, reader: R, label: &Path) -> Result<usize, AnalyzerError> {
+        let (stream, detected) = self.config.open_reader(reader, label)?;
+        self.detected_formats.extend(detected);
+        self.consume(stream, label)
+    }
+
+    // Read all log files in a directory, as selected by the walk options.
+    // In chronological merge mode the files are merged into one timestamp-ordered stream.
     pub fn process_directory(&mut self, path: &Path) -> Result<usize, AnalyzerError> {
+        let files = self.collect_directory(path)?;
+
+        if self.merge {
+            return self.process_files_merged(&files);
+        }
+        self.process_files(&files)
+    }
+
+    // Reads `files` one after the other, or on the worker pool when more than one
+    // worker is configured. Either way the results are added in the order given.
+    pub fn process_files(&mut self, files: &[PathBuf]) -> Result<usize, AnalyzerError> {
+        if self.workers > 1 && files.len() > 1 {
+            return self.process_files_parallel(files);
+        }
+
+        let mut total_success = 0usize;
+        for file_path in files {
+            total_success += self.process_file(file_path)?;
+        }
+        Ok(total_success)
+    }
+
+    // Workers take the next unread file from a shared counter and send back its
+    // records. Outputs that arrive early wait until all files before them are folded
+    // in. On an error, the files before it are kept and the workers stop.
+    fn process_files_parallel(&mut self, files: &[PathBuf]) -> Result<usize, AnalyzerError> {
+        let config = self.config.clone();
+        let retention = self.retention;
+        let filter = self.filter.clone();
+        let empty_stats = self.stats.empty_like();
+        let next = AtomicUsize::new(0);
+        let (tx, rx) = mpsc::channel();
+
+        thread::scope(|scope| {
+            for _ in 0..self.workers.min(files.len()) {
+                let tx = tx.clone();
+                let (config, filter, empty_stats, next) = (&config, &filter, &empty_stats, &next);
+                scope.spawn(move || loop {
+                    let index = next.fetch_add(1, AtomicOrdering::Relaxed);
+                    let Some(path) = files.get(index) else {
+                        break;
+                    };
+                    if tx.send((index, read_file(config, filter, retention, empty_stats, path))).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(tx);
+
+            let mut waiting = BTreeMap::new();
+            let mut next_to_fold = 0;
+            let mut total_success = 0usize;
+            for (index, output) in rx {
+                waiting.insert(index, output);
+                while let Some(output) = waiting.remove(&next_to_fold) {
+                    next_to_fold += 1;
+                    total_success += self.fold(output)?;
+                }
+            }
+            Ok(total_success)
+        })
+    }
+
+    // Adds one file's output as if it had been read here. Returns the number of entries.
+    fn fold(&mut self, output: FileOutput) -> Result<usize, AnalyzerError> {
+        self.detected_formats.extend(output.detected);
+        self.error_total += output.dropped_errors;
+        self.filtered += output.filtered;
+
+        let mut success_count = 0usize;
+        if let Some(stats) = &output.stats {
+            self.stats.merge(stats);
+            success_count += stats.total_entries;
+        }
+        for record in output.records {
+            match record {
+                Record::Entry(entry) => {
+                    if self.ingest(entry) {
+                        success_count += 1;
+                    }
+                }
+                Record::Error(err) => self.record_error(err),
+            }
+        }
+
+        match output.error {
+            Some(e) => Err(e),
+            None => Ok(success_count),
+        }
+    }
+
+    // Lists the files process_directory would read, recording the ones it skips.
+    pub fn collect_directory(&mut self, path: &Path) -> Result<Vec<PathBuf>, AnalyzerError> {
         if !path.exists() {
             return Err(AnalyzerError::InvalidPath {
                 path: path.to_path_buf(),
@@ -86,53 +596,363 @@ impl LogAnalyzer {
             });
         }
 
-        let dir_iter = fs::read_dir(path).map_err(|e| AnalyzerError::IoError {
+        let walk = walk_directory(path, &self.walk).map_err(|reason| AnalyzerError::InvalidPattern { reason })?;
+        self.skipped_files.extend(walk.skipped);
+
+        if walk.files.is_empty() {
+            return Err(AnalyzerError::NoFilesFound {
+                path: path.to_path_buf(),
+            });
+        }
+
+        Ok(walk.files)
+    }
+
+    // Reads `files` as one stream ordered by timestamp using a k-way merge.
+    // Files are first put in rotation order (app.log.2, app.log.1, app.log) so equal
+    // timestamps keep their original order. Each file is expected to be in order
+    // itself; only one pending entry per file is held in memory. With more than
+    // MAX_OPEN_MERGE_INPUTS files, groups of them are first merged into sorted runs
+    // in a temporary directory, and then the runs are merged.
+    pub fn process_files_merged(&mut self, files: &[PathBuf]) -> Result<usize, AnalyzerError> {
+        let mut files = files.to_vec();
+        sort_by_rotation(&mut files);
+        let mut inputs: Vec<MergeInput> = files.into_iter().map(MergeInput::File).collect();
+
+        // Holds the runs until the final merge has read them
+        let spill_dir = if inputs.len() > MAX_OPEN_MERGE_INPUTS {
+            Some(tempfile::tempdir().map_err(|e| AnalyzerError::IoError {
+                path: std::env::temp_dir(),
+                source: e,
+            })?)
+        } else {
+            None
+        };
+        if let Some(dir) = &spill_dir {
+            let mut run_count = 0usize;
+            while inputs.len() > MAX_OPEN_MERGE_INPUTS {
+                // Groups are consecutive, so equal timestamps still keep rotation order
+                let mut runs = Vec::new();
+                let mut rest = inputs.into_iter().peekable();
+                while rest.peek().is_some() {
+                    let group: Vec<MergeInput> = rest.by_ref().take(MAX_OPEN_MERGE_INPUTS).collect();
+                    let path = dir.path().join(format!("run{}.jsonl", run_count));
+                    run_count += 1;
+                    self.merge_into_run(group, &path)?;
+                    runs.push(MergeInput::Run(path));
+                }
+                inputs = runs;
+            }
+        }
+
+        let mut success_count = 0usize;
+        self.merge_inputs(inputs, |analyzer, entry| {
+            if analyzer.ingest(entry) {
+                success_count += 1;
+            }
+            Ok(())
+        })?;
+
+        Ok(success_count)
+    }
+
+    // Merges `inputs` by timestamp, handing each entry to `sink` in order.
+    fn merge_inputs(
+        &mut self,
+        inputs: Vec<MergeInput>,
+        mut sink: impl FnMut(&mut Self, LogEntry) -> Result<(), AnalyzerError>,
+    ) -> Result<(), AnalyzerError> {
+        let mut streams = Vec::with_capacity(inputs.len());
+        for input in inputs {
+            streams.push(self.open_merge_input(input)?);
+        }
+
+        let mut heap = BinaryHeap::with_capacity(streams.len());
+        for (source, stream) in streams.iter_mut().enumerate() {
+            if let Some(entry) = self.next_merged(stream)? {
+                heap.push(Reverse(MergeItem { entry, source }));
+            }
+        }
+
+        while let Some(Reverse(MergeItem { entry, source })) = heap.pop() {
+            sink(self, entry)?;
+
+            if let Some(entry) = self.next_merged(&mut streams[source])? {
+                heap.push(Reverse(MergeItem { entry, source }));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Merges `inputs` into a run file at `path`, for a later pass to merge further.
+    fn merge_into_run(&mut self, inputs: Vec<MergeInput>, path: &Path) -> Result<(), AnalyzerError> {
+        let io_error = |e: io::Error| AnalyzerError::IoError {
             path: path.to_path_buf(),
             source: e,
+        };
+        let mut writer = BufWriter::new(File::create(path).map_err(io_error)?);
+        self.merge_inputs(inputs, |_, entry| {
+            serde_json::to_writer(&mut writer, &entry)
+                .map_err(io::Error::from)
+                .and_then(|()| writer.write_all(b"\n"))
+                .map_err(io_error)
         })?;
+        writer.flush().map_err(io_error)
+    }
 
-        let mut total_success = 0usize;
-        let mut found_log_file = false;
+    fn open_merge_input(&mut self, input: MergeInput) -> Result<MergeStream, AnalyzerError> {
+        match input {
+            MergeInput::File(path) => {
+                let (stream, detected) = self.config.open_file(&path)?;
+                self.detected_formats.extend(detected);
+                Ok(MergeStream::File(Box::new(stream), path))
+            }
+            MergeInput::Run(path) => match File::open(&path) {
+                Ok(file) => Ok(MergeStream::Run(BufReader::new(file), path)),
+                Err(e) => Err(AnalyzerError::IoError { path, source: e }),
+            },
+        }
+    }
+
+    fn next_merged(&mut self, stream: &mut MergeStream) -> Result<Option<LogEntry>, AnalyzerError> {
+        match stream {
+            MergeStream::File(stream, path) => self.next_entry(stream, input_label(path)),
+            MergeStream::Run(reader, path) => {
+                let mut line = String::new();
+                let entry = match reader.read_line(&mut line) {
+                    Ok(0) => Ok(None),
+                    Ok(_) => serde_json::from_str(&line).map(Some).map_err(io::Error::from),
+                    Err(e) => Err(e),
+                };
+                entry.map_err(|e| AnalyzerError::IoError {
+                    path: path.clone(),
+                    source: e,
+                })
+            }
+        }
+    }
+
+    // Drains a stream, keeping entries and parse errors. Returns the number of entries.
+    fn consume<R: BufRead>(&mut self, stream: EntryReader<R>, path: &Path) -> Result<usize, AnalyzerError> {
+        let mut success_count = 0usize;
 
-        for entry in dir_iter {
-            let entry = entry.map_err(|e| AnalyzerError::IoError {
+        for record in stream {
+            let record = record.map_err(|e| AnalyzerError::IoError {
                 path: path.to_path_buf(),
                 source: e,
             })?;
+            match record {
+                Record::Entry(entry) => {
+                    if self.ingest(entry) {
+                        success_count += 1;
+                    }
+                }
+                Record::Error(err) => self.record_error(err),
+            }
+        }
 
-            let file_path = entry.path();
-
-            let is_log_file = file_path.is_file()
-                && file_path
-                    .extension()
-                    .and_then(|s| s.to_str())
-                    .map(|ext| ext.eq_ignore_ascii_case("log"))
-                    .unwrap_or(false);
+        Ok(success_count)
+    }
 
-            if is_log_file {
-                found_log_file = true;
-                total_success += self.process_file(&file_path)?;
+    // Advances a stream to its next entry, keeping the parse errors passed on the way.
+    fn next_entry<R: BufRead>(&mut self, stream: &mut EntryReader<R>, path: &Path) -> Result<Option<LogEntry>, AnalyzerError> {
+        for record in stream {
+            let record = record.map_err(|e| AnalyzerError::IoError {
+                path: path.to_path_buf(),
+                source: e,
+            })?;
+            match record {
+                Record::Entry(entry) => return Ok(Some(entry)),
+                Record::Error(err) => self.record_error(err),
             }
         }
+        Ok(None)
+    }
 
-        if !found_log_file {
-            return Err(AnalyzerError::NoFilesFound {
+    // Start following `path` like `tail -F`. Its current content is read by the
+    // next poll, then whatever is appended after it. Truncation and rotation are
+    // noticed and the file is read again from the start. The path may not exist yet.
+    pub fn follow(&mut self, path: &Path) -> Result<(), AnalyzerError> {
+        if path == Path::new(STDIN_PATH) {
+            return Err(AnalyzerError::InvalidPath {
+                path: path.to_path_buf(),
+                reason: "Standard input cannot be followed".to_string(),
+            });
+        }
+        if path.is_dir() {
+            return Err(AnalyzerError::InvalidPath {
                 path: path.to_path_buf(),
+                reason: "Expected a file path".to_string(),
             });
         }
+        let tail = FileTail::new(path).with_partial_line_grace(self.partial_line_grace);
+        self.followed.push(Followed {
+            tail,
+            parser: None,
+            sample: Vec::new(),
+            sampling_since: Instant::now(),
+            failures: 0,
+        });
+        Ok(())
+    }
 
-        Ok(total_success)
+    // Reads what was written to the followed files since the last poll and
+    // updates the statistics. A file that fails does not stop the others from
+    // being polled. A multi-line entry is only counted once the line after it arrives.
+    pub fn poll(&mut self) -> PollOutcome {
+        let mut outcome = PollOutcome::default();
+        let mut followed = std::mem::take(&mut self.followed);
+        followed.retain_mut(|file| {
+            let (entries, result) = self.poll_one(file);
+            outcome.entries += entries;
+            match result {
+                Ok(()) => file.failures = 0,
+                Err(e) => {
+                    outcome.errors.push(e);
+                    file.failures += 1;
+                    if file.failures >= MAX_FOLLOW_FAILURES {
+                        outcome.stopped.push(file.tail.path().to_path_buf());
+                        return false;
+                    }
+                }
+            }
+            true
+        });
+        self.followed = followed;
+        outcome
+    }
+
+    // Number of files still followed.
+    pub fn followed_count(&self) -> usize {
+        self.followed.len()
+    }
+
+    // Returns the new entries, counted even when the poll failed partway.
+    fn poll_one(&mut self, file: &mut Followed) -> (usize, Result<(), AnalyzerError>) {
+        let mut events = Vec::new();
+        let mut result = file.tail.poll(&mut events).map_err(|e| AnalyzerError::IoError {
+            path: file.tail.path().to_path_buf(),
+            source: e,
+        });
+
+        // Lines wanted for format detection before the parser is created
+        let sample_lines = self.config.detector.as_ref().map_or(0, FormatDetector::sample_lines);
+        let mut records = VecDeque::new();
+        for event in events {
+            match event {
+                TailEvent::Line(line) => match &mut file.parser {
+                    Some(parser) => parser.push_line(&line, &mut records),
+                    None => {
+                        if file.sample.is_empty() {
+                            file.sampling_since = Instant::now();
+                        }
+                        file.sample.push(line);
+                        if file.sample.len() >= sample_lines {
+                            result = result.and(self.start_parser(file, &mut records));
+                        }
+                    }
+                },
+                TailEvent::Reopened => {
+                    // The old content is finished, so its sample is all there is to go on
+                    result = result.and(self.start_parser(file, &mut records));
+                    if let Some(mut parser) = file.parser.take() {
+                        parser.finish(&mut records);
+                    }
+                }
+            }
+        }
+        // A file that grows slowly is detected from fewer lines once the grace period has passed
+        if file.parser.is_none() && file.sampling_since.elapsed() >= self.partial_line_grace {
+            result = result.and(self.start_parser(file, &mut records));
+        }
+
+        let mut success_count = 0usize;
+        for record in records {
+            match record {
+                Record::Entry(entry) => {
+                    if self.ingest(entry) {
+                        success_count += 1;
+                    }
+                }
+                Record::Error(err) => self.record_error(err),
+            }
+        }
+
+        (success_count, result)
+    }
+
+    // Creates a followed file's parser from its sampled lines, then replays them.
+    fn start_parser(&mut self, file: &mut Followed, records: &mut VecDeque<Record>) -> Result<(), AnalyzerError> {
+        if file.sample.is_empty() {
+            return Ok(());
+        }
+        let sample = std::mem::take(&mut file.sample);
+        let (mut parser, detected) = self.config.new_parser(&sample, file.tail.path())?;
+        self.detected_formats.extend(detected);
+        for line in &sample {
+            parser.push_line(line, records);
+        }
+        file.parser = Some(parser);
+        Ok(())
+    }
+
+    // Single sink for parsed entries. Returns false if the filter rejected the entry.
+    fn ingest(&mut self, entry: LogEntry) -> bool {
+        if !self.filter.matches(&entry) {
+            self.filtered += 1;
+            return false;
+        }
+        self.stats.add(&entry);
+        if self.retention.keep_entries {
+            self.entries.push(entry);
+        }
+        true
+    }
+
+    fn record_error(&mut self, err: ParseError) {
+        self.error_total += 1;
+        if self.retention.keeps_error(self.errors.len()) {
+            self.errors.push(err);
+        }
     }
 
     pub fn entries(&self) -> &[LogEntry] {
         &self.entries
     }
 
+    // The kept parse errors; a sample when with_max_parse_errors is set.
     pub fn parse_errors(&self) -> &[ParseError] {
         &self.errors
     }
 
+    // Entries that parsed but were rejected by the filter.
+    pub fn filtered_count(&self) -> usize {
+        self.filtered
+    }
+
+    // All parse errors seen, including the ones not kept.
+    pub fn parse_error_count(&self) -> usize {
+        self.error_total
+    }
+
+    // Files found by process_directory that were not processed, with the reason.
+    pub fn skipped_files(&self) -> &[SkippedFile] {
+        &self.skipped_files
+    }
+
+    // Format chosen for each file when detection is enabled.
+    pub fn detected_formats(&self) -> &[DetectedFormat] {
+        &self.detected_formats
+    }
+
+    // A copy of the statistics so far; see statistics_ref to avoid the clone.
     pub fn statistics(&self) -> Statistics {
-        Statistics::from_entries(&self.entries)
+        self.stats.clone()
+    }
+
+    // Kept up to date as entries are read.
+    pub fn statistics_ref(&self) -> &Statistics {
+        &self.stats
     }
 }
@@ -1,31 +1,113 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
 use std::path::{Path,PathBuf};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 use crate::date_time::DateTime;
 use crate::log_level::LogLevel;
 
-#[derive(Debug)]
+// Machine-readable category of a ParseError, for grouping and filtering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ParseErrorKind {
+    EmptyLine,
+    InvalidEncoding,
+    InvalidSyntax,      // not the shape of the format at all (bad JSON, logfmt, syslog header)
+    MissingTimestamp,
+    BadTimestamp,
+    MissingLevel,
+    MalformedLevel,     // level present but not written the way the format expects
+    UnknownLevel,
+    MissingComponent,
+    MalformedComponent,
+    MissingMessage,
+    MissingField,       // any other required field of the format
+    InvalidField,
+    TypeMismatch,
+    OrphanContinuation,
+}
+
+impl ParseErrorKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ParseErrorKind::EmptyLine => "empty-line",
+            ParseErrorKind::InvalidEncoding => "invalid-encoding",
+            ParseErrorKind::InvalidSyntax => "invalid-syntax",
+            ParseErrorKind::MissingTimestamp => "missing-timestamp",
+            ParseErrorKind::BadTimestamp => "bad-timestamp",
+            ParseErrorKind::MissingLevel => "missing-level",
+            ParseErrorKind::MalformedLevel => "malformed-level",
+            ParseErrorKind::UnknownLevel => "unknown-level",
+            ParseErrorKind::MissingComponent => "missing-component",
+            ParseErrorKind::MalformedComponent => "malformed-component",
+            ParseErrorKind::MissingMessage => "missing-message",
+            ParseErrorKind::MissingField => "missing-field",
+            ParseErrorKind::InvalidField => "invalid-field",
+            ParseErrorKind::TypeMismatch => "type-mismatch",
+            ParseErrorKind::OrphanContinuation => "orphan-continuation",
+        }
+    }
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Error)]
+#[error("{}:{}: {}", .file.display(), .line_number, .reason)]
 pub struct ParseError{
+    pub kind: ParseErrorKind,
     pub file: PathBuf,
     pub content: String,
     pub line_number: usize,
-    pub reason: String
+    pub reason: String,
+    pub span: Option<Range<usize>>, // byte range of the offending token within `content`
 }
 
-#[derive(Debug)]
+impl ParseError {
+    pub fn new(kind: ParseErrorKind, file: &Path, content: &str, line_number: usize, reason: impl Into<String>) -> Self {
+        ParseError {
+            kind,
+            file: file.to_path_buf(),
+            content: content.to_string(),
+            line_number,
+            reason: reason.into(),
+            span: None,
+        }
+    }
+
+    pub fn with_span(mut self, span: Range<usize>) -> Self {
+        self.span = Some(span);
+        self
+    }
+}
+
+// Byte range of `part` within `line`; `part` must be a sub-slice of `line`.
+pub(crate) fn span_of(line: &str, part: &str) -> Range<usize> {
+    let start = (part.as_ptr() as usize).saturating_sub(line.as_ptr() as usize).min(line.len());
+    start..(start + part.len()).min(line.len())
+}
+
+// Zero-width span at the end of `line`, for fields that are missing altogether.
+pub(crate) fn end_span(line: &str) -> Range<usize> {
+    line.len()..line.len()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LogEntry {
  pub timestamp: DateTime,
  pub level: LogLevel,
  pub component: String,
  pub message: String,
  pub source_file: PathBuf,
+ pub fields: BTreeMap<String, String>, // extra key/values from structured formats
 }
 
-fn missing_field_error(source_file: &Path, line: &str,  line_number:usize, field: &str) -> ParseError {
-    ParseError {
-        file: source_file.to_path_buf(),
-        content: line.to_string(),
-        line_number,
-        reason: format!("Missing required field: {}", field),
-    }
+fn missing_field_error(kind: ParseErrorKind, source_file: &Path, line: &str,  line_number:usize, field: &str) -> ParseError {
+    ParseError::new(kind, source_file, line, line_number, format!("Missing required field: {}", field))
+        .with_span(end_span(line))
 }
 
 
@@ -35,92 +117,82 @@ pub fn parse_log_line(line: &str, source_file: &Path, line_number:usize) -> Resu
     let line = line.trim();
 
     if line.is_empty() {
-        return Err(ParseError{
-            file:source_file.to_path_buf(),
-            content:line.to_string(),
-            line_number,
-            reason:"Empty line".to_string()});
+        return Err(ParseError::new(ParseErrorKind::EmptyLine, source_file, line, line_number, "Empty line"));
     }
     let line_parts:Vec<&str> = line.split_whitespace().collect();
 
-    // Extracting Timestamp
-    let date = *line_parts.get(0)
-                                .ok_or_else(|| missing_field_error(source_file, line, line_number, "date"))?;
-
-    let time = *line_parts.get(1)
-                                .ok_or_else(|| missing_field_error(source_file, line, line_number ,"time"))?;
+    // Extracting Timestamp, either "date time" or a single ISO 8601 token
+    let date = *line_parts.first()
+                                .ok_or_else(|| missing_field_error(ParseErrorKind::MissingTimestamp, source_file, line, line_number, "date"))?;
 
-     let dt_str = format!("{} {}", date, time);
+    let (dt_str, dt_span, next) = if date.contains(['T', 't']) {
+        (date.to_string(), span_of(line, date), 1)
+    } else {
+        let time = *line_parts.get(1)
+                                .ok_or_else(|| missing_field_error(ParseErrorKind::MissingTimestamp, source_file, line, line_number ,"time"))?;
+        (format!("{} {}", date, time), span_of(line, date).start..span_of(line, time).end, 2)
+    };
 
-    let timestamp: DateTime = dt_str.parse().map_err(|e| ParseError {
-        file: source_file.to_path_buf(),
-        content: line.to_string(),
-        line_number,
-        reason: format!("Invalid timestamp: {}", e),
+    let timestamp: DateTime = dt_str.parse().map_err(|e| {
+        ParseError::new(ParseErrorKind::BadTimestamp, source_file, line, line_number, format!("Invalid timestamp: {}", e))
+            .with_span(dt_span)
     })?;
 
     // Log Level
-    let log_level = *line_parts.get(2)
-                                        .ok_or_else(|| missing_field_error(source_file, line, line_number,"log level"))?;
+    let log_level = *line_parts.get(next)
+                                        .ok_or_else(|| missing_field_error(ParseErrorKind::MissingLevel, source_file, line, line_number,"log level"))?;
+    let level_span = span_of(line, log_level);
 
     if !log_level.starts_with('[') || !log_level.ends_with(']') {
-        return Err(ParseError {
-            file: source_file.to_path_buf(),
-            content: line.to_string(),
+        return Err(ParseError::new(
+            ParseErrorKind::MalformedLevel,
+            source_file,
+            line,
             line_number,
-            reason: format!("level must be in [LEVEL] format, got '{}'", log_level),
-        });
+            format!("level must be in [LEVEL] format, got '{}'", log_level),
+        ).with_span(level_span));
     }
 
     let log_level = log_level.trim_start_matches('[').trim_end_matches(']');
 
-    let level: LogLevel = log_level.parse().map_err(|e| ParseError {
-        file: source_file.to_path_buf(),
-        content: line.to_string(),
-        line_number,
-        reason: format!("Invalid log level: {}", e),
+    let level: LogLevel = log_level.parse().map_err(|e| {
+        ParseError::new(ParseErrorKind::UnknownLevel, source_file, line, line_number, format!("Invalid log level: {}", e))
+            .with_span(level_span)
     })?;
 
-    // Component parts 
-    let component_token = *line_parts.get(3)
-                                            .ok_or_else(|| missing_field_error(source_file, line, line_number, "component"))?;
+    // Component parts
+    let component_token = *line_parts.get(next + 1)
+                                            .ok_or_else(|| missing_field_error(ParseErrorKind::MissingComponent, source_file, line, line_number, "component"))?;
 
     if !component_token.ends_with(':') {
-        return Err(ParseError {
-            file: source_file.to_path_buf(),
-            content: line.to_string(),
+        return Err(ParseError::new(
+            ParseErrorKind::MalformedComponent,
+            source_file,
+            line,
             line_number,
-            reason: format!("component must end with ':', got '{}'", component_token),
-        });
+            format!("component must end with ':', got '{}'", component_token),
+        ).with_span(span_of(line, component_token)));
     }
 
     let component = component_token.trim_end_matches(':').to_string();
 
     // Messages
-    if line_parts.len() < 5 {
-        return Err(missing_field_error(source_file, line, line_number, "message"));
+    if line_parts.len() < next + 3 {
+        return Err(missing_field_error(ParseErrorKind::MissingMessage, source_file, line, line_number, "message"));
     }
 
-    let message = line_parts[4..].join(" ");
+    let message = line_parts[next + 2..].join(" ");
 
-    if message.trim().is_empty() {
-        return Err(ParseError {
-            file: source_file.to_path_buf(),
-            content: line.to_string(),
-            line_number,
-            reason: "Missing message field".to_string(),
-        });
-    }
 
-    
     let log_entry:LogEntry = LogEntry{
         timestamp,
         level,
         component,
         message,
-        source_file:source_file.to_path_buf()
+        source_file:source_file.to_path_buf(),
+        fields: BTreeMap::new(),
     };
 
      Ok(log_entry)
 
-}
\ No newline at end of file
+}
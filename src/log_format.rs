@@ -0,0 +1,714 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::UNIX_EPOCH;
+
+use serde_json::{Map, Value};
+
+use crate::date_time::DateTime;
+use crate::log_entry::{end_span, parse_log_line, span_of, LogEntry, ParseError, ParseErrorKind};
+use crate::log_level::LogLevel;
+
+// A line-oriented log layout that can turn one line into a LogEntry.
+pub trait LogFormat: Send + Sync {
+    fn name(&self) -> &str;
+    fn parse_line(&self, line: &str, source_file: &Path, line_number: usize) -> Result<LogEntry, ParseError>;
+}
+
+// All built-in formats, default first.
+pub fn builtin_formats() -> Vec<Arc<dyn LogFormat>> {
+    builtin_formats_with(SyslogFormat::default())
+}
+
+// The built-in formats with `syslog` configured, e.g. with a fixed year.
+pub fn builtin_formats_with(syslog: SyslogFormat) -> Vec<Arc<dyn LogFormat>> {
+    vec![
+        Arc::new(BracketedFormat),
+        Arc::new(LogfmtFormat),
+        Arc::new(JsonFormat::default()),
+        Arc::new(syslog),
+        Arc::new(NginxAccessFormat::default()),
+    ]
+}
+
+pub fn format_by_name(name: &str) -> Option<Arc<dyn LogFormat>> {
+    builtin_formats()
+        .into_iter()
+        .find(|f| f.name().eq_ignore_ascii_case(name))
+}
+
+// Level names used by other ecosystems on top of the ones LogLevel understands.
+fn parse_level_alias(s: &str) -> Result<LogLevel, String> {
+    match s.to_lowercase().as_str() {
+        "warning" => Ok(LogLevel::Warn),
+        "err" => Ok(LogLevel::Error),
+        "crit" | "critical" | "alert" | "emerg" | "panic" => Ok(LogLevel::Fatal),
+        other => other.parse(),
+    }
+}
+
+// ---------------- BRACKETED ----------------
+
+// 2024-01-15 10:23:45 [ERROR] storage: Failed to mount filesystem /dev/sda1
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BracketedFormat;
+
+impl LogFormat for BracketedFormat {
+    fn name(&self) -> &str {
+        "bracketed"
+    }
+
+    fn parse_line(&self, line: &str, source_file: &Path, line_number: usize) -> Result<LogEntry, ParseError> {
+        parse_log_line(line, source_file, line_number)
+    }
+}
+
+// ---------------- LOGFMT ----------------
+
+// ts=2024-01-15T10:23:45Z level=error component=storage msg="Failed to mount" device=/dev/sda1
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LogfmtFormat;
+
+const LOGFMT_TIMESTAMP_KEYS: [&str; 3] = ["ts", "time", "timestamp"];
+const LOGFMT_LEVEL_KEYS: [&str; 3] = ["level", "lvl", "severity"];
+const LOGFMT_COMPONENT_KEYS: [&str; 5] = ["component", "module", "service", "app", "logger"];
+const LOGFMT_MESSAGE_KEYS: [&str; 2] = ["msg", "message"];
+
+// Splits a logfmt line into key/value pairs; values may be double-quoted with \" escapes.
+fn logfmt_pairs(line: &str) -> Result<Vec<(String, String)>, String> {
+    let mut pairs = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let mut key = String::new();
+        while let Some(&c) = chars.peek() {
+            if c == '=' || c.is_whitespace() {
+                break;
+            }
+            key.push(c);
+            chars.next();
+        }
+        if key.is_empty() {
+            return Err("expected key before '='".to_string());
+        }
+
+        let mut value = String::new();
+        if chars.peek() == Some(&'=') {
+            chars.next();
+            if chars.peek() == Some(&'"') {
+                chars.next();
+                let mut closed = false;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => {
+                            if let Some(escaped) = chars.next() {
+                                value.push(escaped);
+                            }
+                        }
+                        '"' => {
+                            closed = true;
+                            break;
+                        }
+                        _ => value.push(c),
+                    }
+                }
+                if !closed {
+                    return Err(format!("unterminated quoted value for key '{}'", key));
+                }
+            } else {
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() {
+                        break;
+                    }
+                    value.push(c);
+                    chars.next();
+                }
+            }
+        }
+        pairs.push((key, value));
+    }
+
+    Ok(pairs)
+}
+
+// Removes the first of `keys` present; returns the key used and its value.
+fn take_first<'k>(pairs: &mut BTreeMap<String, String>, keys: &[&'k str]) -> Option<(&'k str, String)> {
+    keys.iter().find_map(|k| pairs.remove(*k).map(|v| (*k, v)))
+}
+
+// Byte range of the `key=value` pair for `key` in a logfmt line.
+fn logfmt_pair_span(line: &str, key: &str) -> Range<usize> {
+    let needle = format!("{}=", key);
+    let start = line
+        .match_indices(&needle)
+        .map(|(i, _)| i)
+        .find(|&i| i == 0 || line[..i].ends_with(char::is_whitespace));
+    let Some(start) = start else {
+        return end_span(line);
+    };
+
+    let value = &line[start + needle.len()..];
+    let len = match value.strip_prefix('"') {
+        Some(quoted) => {
+            let mut escaped = false;
+            quoted
+                .char_indices()
+                .find(|&(_, c)| {
+                    let close = c == '"' && !escaped;
+                    escaped = c == '\\' && !escaped;
+                    close
+                })
+                .map(|(i, _)| i + 2)
+                .unwrap_or(value.len())
+        }
+        None => value.find(char::is_whitespace).unwrap_or(value.len()),
+    };
+    start..start + needle.len() + len
+}
+
+impl LogFormat for LogfmtFormat {
+    fn name(&self) -> &str {
+        "logfmt"
+    }
+
+    fn parse_line(&self, line: &str, source_file: &Path, line_number: usize) -> Result<LogEntry, ParseError> {
+        let line = line.trim();
+        let err = |kind: ParseErrorKind, reason: String, span: Range<usize>| {
+            ParseError::new(kind, source_file, line, line_number, reason).with_span(span)
+        };
+        if line.is_empty() {
+            return Err(ParseError::new(ParseErrorKind::EmptyLine, source_file, line, line_number, "Empty line"));
+        }
+
+        let pairs = logfmt_pairs(line)
+            .map_err(|e| err(ParseErrorKind::InvalidSyntax, format!("Invalid logfmt: {}", e), 0..line.len()))?;
+        if pairs.iter().all(|(_, v)| v.is_empty()) {
+            return Err(err(ParseErrorKind::InvalidSyntax, "Invalid logfmt: no key=value pairs".to_string(), 0..line.len()));
+        }
+        let mut fields: BTreeMap<String, String> = pairs.into_iter().collect();
+
+        let (key, ts) = take_first(&mut fields, &LOGFMT_TIMESTAMP_KEYS).ok_or_else(|| {
+            err(ParseErrorKind::MissingTimestamp, "Missing required field: timestamp".to_string(), end_span(line))
+        })?;
+        let timestamp: DateTime = ts.parse().map_err(|e| {
+            err(ParseErrorKind::BadTimestamp, format!("Invalid timestamp: {}", e), logfmt_pair_span(line, key))
+        })?;
+
+        let (key, level) = take_first(&mut fields, &LOGFMT_LEVEL_KEYS).ok_or_else(|| {
+            err(ParseErrorKind::MissingLevel, "Missing required field: log level".to_string(), end_span(line))
+        })?;
+        let level = parse_level_alias(&level).map_err(|e| {
+            err(ParseErrorKind::UnknownLevel, format!("Invalid log level: {}", e), logfmt_pair_span(line, key))
+        })?;
+
+        let (_, message) = take_first(&mut fields, &LOGFMT_MESSAGE_KEYS)
+            .filter(|(_, m)| !m.trim().is_empty())
+            .ok_or_else(|| err(ParseErrorKind::MissingMessage, "Missing required field: message".to_string(), end_span(line)))?;
+
+        let component = take_first(&mut fields, &LOGFMT_COMPONENT_KEYS)
+            .map(|(_, c)| c)
+            .unwrap_or_else(|| "unknown".to_string());
+
+        Ok(LogEntry {
+            timestamp,
+            level,
+            component,
+            message,
+            source_file: source_file.to_path_buf(),
+            fields,
+        })
+    }
+}
+
+// ---------------- JSON LINES ----------------
+
+// Keys holding the LogEntry fields in each JSON object.
+#[derive(Debug, Clone)]
+pub struct JsonFieldNames {
+    pub timestamp: String,
+    pub level: String,
+    pub component: String,
+    pub message: String,
+}
+
+impl Default for JsonFieldNames {
+    fn default() -> Self {
+        JsonFieldNames {
+            timestamp: "ts".to_string(),
+            level: "level".to_string(),
+            component: "component".to_string(),
+            message: "msg".to_string(),
+        }
+    }
+}
+
+// {"ts":"2024-01-15T10:23:45Z","level":"error","component":"storage","msg":"Failed to mount"}
+// Keys other than the four mapped ones are kept in LogEntry.fields; non-string values
+// are stored as their JSON text.
+#[derive(Debug, Clone, Default)]
+pub struct JsonFormat {
+    pub field_names: JsonFieldNames,
+}
+
+fn json_type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+// Byte range of the value stored under `key` in a JSON object line.
+fn json_value_span(line: &str, key: &str) -> Range<usize> {
+    let needle = format!("\"{}\"", key);
+    for (i, _) in line.match_indices(&needle) {
+        let after = &line[i + needle.len()..];
+        let Some(value) = after.trim_start().strip_prefix(':') else {
+            continue;
+        };
+        let value = value.trim_start();
+        let start = line.len() - value.len();
+        let len = if let Some(quoted) = value.strip_prefix('"') {
+            let mut escaped = false;
+            quoted
+                .char_indices()
+                .find(|&(_, c)| {
+                    let close = c == '"' && !escaped;
+                    escaped = c == '\\' && !escaped;
+                    close
+                })
+                .map(|(i, _)| i + 2)
+                .unwrap_or(value.len())
+        } else {
+            value.find([',', '}']).unwrap_or(value.len()).max(1)
+        };
+        return start..(start + len).min(line.len());
+    }
+    end_span(line)
+}
+
+// Removes `key` from the object and requires it to be a string.
+fn take_json_string(object: &mut Map<String, Value>, key: &str, field: &str) -> Result<String, (ParseErrorKind, String)> {
+    match object.remove(key) {
+        Some(Value::String(s)) => Ok(s),
+        Some(other) => Err((
+            ParseErrorKind::TypeMismatch,
+            format!("Type mismatch: {} key '{}' must be a string, got {}", field, key, json_type_name(&other)),
+        )),
+        None => {
+            let kind = match field {
+                "timestamp" => ParseErrorKind::MissingTimestamp,
+                "log level" => ParseErrorKind::MissingLevel,
+                "component" => ParseErrorKind::MissingComponent,
+                "message" => ParseErrorKind::MissingMessage,
+                _ => ParseErrorKind::MissingField,
+            };
+            Err((kind, format!("Missing required field: {} (key '{}')", field, key)))
+        }
+    }
+}
+
+impl LogFormat for JsonFormat {
+    fn name(&self) -> &str {
+        "json"
+    }
+
+    fn parse_line(&self, line: &str, source_file: &Path, line_number: usize) -> Result<LogEntry, ParseError> {
+        let line = line.trim();
+        let err = |kind: ParseErrorKind, reason: String, span: Range<usize>| {
+            ParseError::new(kind, source_file, line, line_number, reason).with_span(span)
+        };
+        if line.is_empty() {
+            return Err(ParseError::new(ParseErrorKind::EmptyLine, source_file, line, line_number, "Empty line"));
+        }
+
+        let mut object = match serde_json::from_str::<Value>(line) {
+            Ok(Value::Object(object)) => object,
+            Ok(other) => {
+                let reason = format!("Invalid JSON: expected an object, got {}", json_type_name(&other));
+                return Err(err(ParseErrorKind::InvalidSyntax, reason, 0..line.len()));
+            }
+            Err(e) => {
+                // serde_json reports a 1-based column on the (single) line
+                let at = e.column().saturating_sub(1).min(line.len());
+                let at = (0..=at).rev().find(|&i| line.is_char_boundary(i)).unwrap_or(0);
+                return Err(err(ParseErrorKind::InvalidSyntax, format!("Invalid JSON: {}", e), at..at));
+            }
+        };
+
+        let names = &self.field_names;
+        let take = |object: &mut Map<String, Value>, key: &str, field: &str| {
+            take_json_string(object, key, field)
+                .map_err(|(kind, reason)| err(kind, reason, json_value_span(line, key)))
+        };
+
+        let ts = take(&mut object, &names.timestamp, "timestamp")?;
+        let timestamp: DateTime = ts.parse().map_err(|e| {
+            err(ParseErrorKind::BadTimestamp, format!("Invalid timestamp: {}", e), json_value_span(line, &names.timestamp))
+        })?;
+
+        let level = take(&mut object, &names.level, "log level")?;
+        let level = parse_level_alias(&level).map_err(|e| {
+            err(ParseErrorKind::UnknownLevel, format!("Invalid log level: {}", e), json_value_span(line, &names.level))
+        })?;
+
+        let component = take(&mut object, &names.component, "component")?;
+        let message = take(&mut object, &names.message, "message")?;
+
+        let fields = object
+            .into_iter()
+            .map(|(key, value)| match value {
+                Value::String(s) => (key, s),
+                other => (key, other.to_string()),
+            })
+            .collect();
+
+        Ok(LogEntry {
+            timestamp,
+            level,
+            component,
+            message,
+            source_file: source_file.to_path_buf(),
+            fields,
+        })
+    }
+}
+
+// ---------------- SYSLOG ----------------
+
+// RFC 5424: <34>1 2024-01-15T10:23:45.003Z host app 1234 ID47 - Failed to mount
+// RFC 3164: <34>Jan 15 10:23:45 host app[1234]: Failed to mount
+// RFC 3164 timestamps carry no year. They get the configured year (with_year), or
+// else the year the file was last modified (UTC), so the result does not depend on
+// when the analysis runs. A line that would then be later than the modification
+// time is from the year before, as in a file running from December into January.
+// Input without a modification time, such as stdin, needs a year.
+#[derive(Debug, Clone, Default)]
+pub struct SyslogFormat {
+    year: Option<u16>,
+    file_modified: Arc<Mutex<HashMap<PathBuf, Option<DateTime>>>>, // modification time per file, looked up once
+}
+
+// How far past the file's modification time a line may be before it is taken to be
+// from the year before. Covers the time zone of the (offset-less) syslog timestamps.
+const YEAR_ROLLOVER_TOLERANCE_SECS: i64 = 24 * 60 * 60;
+
+impl SyslogFormat {
+    pub fn with_year(year: u16) -> Self {
+        SyslogFormat { year: Some(year), ..Self::default() }
+    }
+
+    fn modified(&self, source_file: &Path) -> Option<DateTime> {
+        let mut file_modified = self.file_modified.lock().unwrap_or_else(|e| e.into_inner());
+        file_modified
+            .entry(source_file.to_path_buf())
+            .or_insert_with(|| {
+                let modified = fs::metadata(source_file).and_then(|m| m.modified()).ok()?;
+                let secs = modified.duration_since(UNIX_EPOCH).ok()?.as_secs();
+                DateTime::from_unix_seconds(secs as i64).ok()
+            })
+            .clone()
+    }
+}
+
+// Takes `n` whitespace-separated tokens off the front of `s`; returns them and the rest.
+fn split_tokens(s: &str, n: usize) -> Option<(Vec<&str>, &str)> {
+    let mut tokens = Vec::with_capacity(n);
+    let mut rest = s;
+    for _ in 0..n {
+        rest = rest.trim_start();
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        if end == 0 {
+            return None;
+        }
+        tokens.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    Some((tokens, rest.trim_start()))
+}
+
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+fn month_number(name: &str) -> Option<u8> {
+    MONTHS.iter().position(|m| m.eq_ignore_ascii_case(name)).map(|i| i as u8 + 1)
+}
+
+// Maps a syslog severity (PRI & 7) onto LogLevel.
+fn syslog_severity(severity: u8) -> LogLevel {
+    match severity {
+        0..=2 => LogLevel::Fatal,
+        3 => LogLevel::Error,
+        4 => LogLevel::Warn,
+        5 | 6 => LogLevel::Info,
+        _ => LogLevel::Debug,
+    }
+}
+
+impl SyslogFormat {
+    fn parse_5424(&self, rest: &str, level: LogLevel, source_file: &Path, line: &str, line_number: usize) -> Result<LogEntry, ParseError> {
+        let err = |kind: ParseErrorKind, reason: String, span: Range<usize>| {
+            ParseError::new(kind, source_file, line, line_number, reason).with_span(span)
+        };
+        let mut parts = rest.splitn(6, ' ');
+        let (ts, host, app, procid, msgid, tail) = match (parts.next(), parts.next(), parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(ts), Some(host), Some(app), Some(procid), Some(msgid), Some(tail)) => (ts, host, app, procid, msgid, tail),
+            _ => return Err(err(ParseErrorKind::InvalidSyntax, "Missing required field: syslog header".to_string(), end_span(line))),
+        };
+
+        let timestamp: DateTime = ts
+            .parse()
+            .map_err(|e| err(ParseErrorKind::BadTimestamp, format!("Invalid timestamp: {}", e), span_of(line, ts)))?;
+
+        // Skip structured data: "-" or one or more [..] blocks
+        let message = if let Some(msg) = tail.strip_prefix('-') {
+            msg
+        } else if tail.starts_with('[') {
+            match tail.find("] ") {
+                Some(idx) => &tail[idx + 1..],
+                None => "",
+            }
+        } else {
+            tail
+        };
+        let message = message.trim();
+        if message.is_empty() {
+            return Err(err(ParseErrorKind::MissingMessage, "Missing required field: message".to_string(), end_span(line)));
+        }
+
+        let mut fields = BTreeMap::new();
+        for (key, value) in [("host", host), ("pid", procid), ("msgid", msgid)] {
+            if value != "-" {
+                fields.insert(key.to_string(), value.to_string());
+            }
+        }
+
+        Ok(LogEntry {
+            timestamp,
+            level,
+            component: app.to_string(),
+            message: message.to_string(),
+            source_file: source_file.to_path_buf(),
+            fields,
+        })
+    }
+
+    fn parse_3164(&self, rest: &str, level: LogLevel, source_file: &Path, line: &str, line_number: usize) -> Result<LogEntry, ParseError> {
+        let err = |kind: ParseErrorKind, reason: String, span: Range<usize>| {
+            ParseError::new(kind, source_file, line, line_number, reason).with_span(span)
+        };
+        let (parts, tail) = split_tokens(rest, 4).ok_or_else(|| {
+            err(ParseErrorKind::InvalidSyntax, "Missing required field: syslog header".to_string(), end_span(line))
+        })?;
+
+        let month = month_number(parts[0]).ok_or_else(|| {
+            err(ParseErrorKind::BadTimestamp, format!("Invalid timestamp: unknown month '{}'", parts[0]), span_of(line, parts[0]))
+        })?;
+        let day: u8 = parts[1].parse().map_err(|_| {
+            err(ParseErrorKind::BadTimestamp, format!("Invalid timestamp: bad day '{}'", parts[1]), span_of(line, parts[1]))
+        })?;
+        let ts_span = span_of(line, parts[0]).start..span_of(line, parts[2]).end;
+        let modified = if self.year.is_none() { self.modified(source_file) } else { None };
+        let year = self.year.or(modified.as_ref().map(|m| m.year)).ok_or_else(|| {
+            err(ParseErrorKind::BadTimestamp, "Invalid timestamp: no year, and none configured".to_string(), ts_span.clone())
+        })?;
+        let with_year = |year: u16| {
+            format!("{:04}-{:02}-{:02} {}", year, month, day, parts[2])
+                .parse::<DateTime>()
+                .map_err(|e| err(ParseErrorKind::BadTimestamp, format!("Invalid timestamp: {}", e), ts_span.clone()))
+        };
+        let mut timestamp = with_year(year)?;
+        if let Some(modified) = &modified
+            && timestamp.unix_seconds() > modified.unix_seconds() + YEAR_ROLLOVER_TOLERANCE_SECS
+        {
+            timestamp = with_year(year - 1)?;
+        }
+
+        let host = parts[3];
+        let (tag, message) = tail.split_once(':').ok_or_else(|| {
+            err(ParseErrorKind::MissingComponent, "Missing required field: component".to_string(), span_of(line, tail))
+        })?;
+        let message = message.trim();
+        if message.is_empty() {
+            return Err(err(ParseErrorKind::MissingMessage, "Missing required field: message".to_string(), end_span(line)));
+        }
+
+        let mut fields = BTreeMap::new();
+        fields.insert("host".to_string(), host.to_string());
+        let component = match tag.split_once('[') {
+            Some((name, pid)) => {
+                fields.insert("pid".to_string(), pid.trim_end_matches(']').to_string());
+                name
+            }
+            None => tag,
+        };
+        if component.is_empty() || component.contains(char::is_whitespace) {
+            return Err(err(ParseErrorKind::MalformedComponent, format!("Invalid component: '{}'", tag), span_of(line, tag)));
+        }
+
+        Ok(LogEntry {
+            timestamp,
+            level,
+            component: component.to_string(),
+            message: message.to_string(),
+            source_file: source_file.to_path_buf(),
+            fields,
+        })
+    }
+}
+
+impl LogFormat for SyslogFormat {
+    fn name(&self) -> &str {
+        "syslog"
+    }
+
+    fn parse_line(&self, line: &str, source_file: &Path, line_number: usize) -> Result<LogEntry, ParseError> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Err(ParseError::new(ParseErrorKind::EmptyLine, source_file, line, line_number, "Empty line"));
+        }
+
+        // Optional <PRI> prefix
+        let (level, rest) = match line.strip_prefix('<').and_then(|r| r.split_once('>')) {
+            Some((pri, rest)) => {
+                let pri: u8 = pri.parse().map_err(|_| {
+                    ParseError::new(
+                        ParseErrorKind::UnknownLevel,
+                        source_file,
+                        line,
+                        line_number,
+                        format!("Invalid log level: bad priority '<{}>'", pri),
+                    )
+                    .with_span(span_of(line, pri))
+                })?;
+                (syslog_severity(pri & 7), rest)
+            }
+            None => (LogLevel::Info, line),
+        };
+
+        match rest.strip_prefix("1 ") {
+            Some(rest) => self.parse_5424(rest, level, source_file, line, line_number),
+            None => self.parse_3164(rest, level, source_file, line, line_number),
+        }
+    }
+}
+
+// ---------------- NGINX ACCESS ----------------
+
+// Combined log format:
+// 127.0.0.1 - - [15/Jan/2024:10:23:45 +0000] "GET /index.html HTTP/1.1" 200 612 "-" "curl/8.0"
+// Level follows the status code: 5xx => ERROR, 4xx => WARN, anything else => INFO.
+#[derive(Debug, Clone)]
+pub struct NginxAccessFormat {
+    pub component: String,
+}
+
+impl Default for NginxAccessFormat {
+    fn default() -> Self {
+        NginxAccessFormat { component: "nginx".to_string() }
+    }
+}
+
+// 15/Jan/2024:10:23:45 +0000
+fn parse_nginx_time(s: &str) -> Result<DateTime, String> {
+    let (stamp, offset) = s.split_once(' ').ok_or("missing UTC offset")?;
+    let mut it = stamp.splitn(4, ['/', ':']);
+    let (day, month, year, time) = match (it.next(), it.next(), it.next(), it.next()) {
+        (Some(d), Some(m), Some(y), Some(t)) => (d, m, y, t),
+        _ => return Err("expected dd/Mon/yyyy:HH:MM:SS".to_string()),
+    };
+    let month = month_number(month).ok_or_else(|| format!("unknown month '{}'", month))?;
+    format!("{}-{:02}-{} {}{}", year, month, day, time, offset).parse()
+}
+
+// Takes a double-quoted string from the start of `s`; returns it and the remainder.
+fn take_quoted(s: &str) -> Option<(&str, &str)> {
+    let s = s.trim_start().strip_prefix('"')?;
+    let end = s.find('"')?;
+    Some((&s[..end], &s[end + 1..]))
+}
+
+impl LogFormat for NginxAccessFormat {
+    fn name(&self) -> &str {
+        "nginx"
+    }
+
+    fn parse_line(&self, line: &str, source_file: &Path, line_number: usize) -> Result<LogEntry, ParseError> {
+        let line = line.trim();
+        let err = |kind: ParseErrorKind, reason: &str, span: Range<usize>| {
+            ParseError::new(kind, source_file, line, line_number, reason).with_span(span)
+        };
+        if line.is_empty() {
+            return Err(ParseError::new(ParseErrorKind::EmptyLine, source_file, line, line_number, "Empty line"));
+        }
+
+        let missing_time = || err(ParseErrorKind::MissingTimestamp, "Missing required field: time", end_span(line));
+        let (client, rest) = line.split_once(" [").ok_or_else(missing_time)?;
+        let (time, rest) = rest.split_once(']').ok_or_else(missing_time)?;
+        let timestamp = parse_nginx_time(time).map_err(|e| {
+            err(ParseErrorKind::BadTimestamp, &format!("Invalid timestamp: {}", e), span_of(line, time))
+        })?;
+
+        let mut client_parts = client.split_whitespace();
+        let remote_addr = client_parts.next().ok_or_else(|| {
+            err(ParseErrorKind::MissingField, "Missing required field: remote address", 0..0)
+        })?;
+        let remote_user = client_parts.nth(1).unwrap_or("-");
+
+        let (request, rest) = take_quoted(rest).ok_or_else(|| {
+            err(ParseErrorKind::MissingField, "Missing required field: request", span_of(line, rest))
+        })?;
+        let (status_parts, after_bytes) = split_tokens(rest, 2).ok_or_else(|| {
+            err(ParseErrorKind::MissingField, "Missing required field: status", end_span(line))
+        })?;
+        let status: u16 = status_parts[0].parse().map_err(|_| {
+            err(ParseErrorKind::InvalidField, &format!("Invalid status code '{}'", status_parts[0]), span_of(line, status_parts[0]))
+        })?;
+        let bytes = status_parts[1];
+
+        let level = match status {
+            500..=599 => LogLevel::Error,
+            400..=499 => LogLevel::Warn,
+            _ => LogLevel::Info,
+        };
+
+        let mut fields = BTreeMap::new();
+        fields.insert("remote_addr".to_string(), remote_addr.to_string());
+        if remote_user != "-" {
+            fields.insert("remote_user".to_string(), remote_user.to_string());
+        }
+        fields.insert("status".to_string(), status.to_string());
+        fields.insert("bytes".to_string(), bytes.to_string());
+        let mut request_parts = request.split_whitespace();
+        if let (Some(method), Some(path)) = (request_parts.next(), request_parts.next()) {
+            fields.insert("method".to_string(), method.to_string());
+            fields.insert("path".to_string(), path.to_string());
+        }
+        if let Some((referer, tail)) = take_quoted(after_bytes) {
+            fields.insert("referer".to_string(), referer.to_string());
+            if let Some((user_agent, _)) = take_quoted(tail) {
+                fields.insert("user_agent".to_string(), user_agent.to_string());
+            }
+        }
+
+        Ok(LogEntry {
+            timestamp,
+            level,
+            component: self.component.clone(),
+            message: format!("{} {}", request, status),
+            source_file: source_file.to_path_buf(),
+            fields,
+        })
+    }
+}
@@ -1,13 +1,25 @@
-use std::path::PathBuf;
-mod date_time;
-mod log_level;
-mod log_entry;
-mod statistics_aggregator;
-mod log_analyzer;
-use log_analyzer::LogAnalyzer;
-
-mod report;
-use report::{print_report, print_report_json};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+use log_analyzer::log_analyzer::{LogAnalyzer, MAX_FOLLOW_FAILURES};
+use log_analyzer::date_time::DateTime;
+use log_analyzer::diagnostics::render_diagnostics;
+use log_analyzer::filter::{EntryFilter, MessageMatch};
+use log_analyzer::follow::PARTIAL_LINE_GRACE;
+use log_analyzer::log_level::LogLevel;
+use log_analyzer::format_detector::FormatDetector;
+use log_analyzer::log_format::{builtin_formats_with, SyslogFormat};
+use log_analyzer::multiline::EntryStart;
+use log_analyzer::query::Query;
+use log_analyzer::timeseries::BucketWidth;
+use log_analyzer::anomaly::{detect_anomalies, Anomaly, AnomalyConfig};
+use log_analyzer::date_time::parse_duration;
+use log_analyzer::gaps::{find_gaps, Gap, GapConfig};
+use log_analyzer::statistics_aggregator::Statistics;
+use log_analyzer::diff::{compare, DiffConfig};
+use log_analyzer::snapshot::{load_snapshot, save_snapshot};
+use log_analyzer::report::{print_diff, print_diff_json, print_report, print_report_json, print_report_json_line, ReportContext};
+use log_analyzer::walk::{SymlinkPolicy, WalkOptions};
 
 use clap::Parser;
 
@@ -16,41 +28,501 @@ struct Cli {
     paths: Vec<PathBuf>,
     #[arg(long, default_value = "text")]
     format: String,
+    /// Layout of the input lines: bracketed, logfmt, json, syslog, nginx, or auto to detect per file
+    #[arg(long, default_value = "bracketed")]
+    input_format: String,
+    /// Year for syslog timestamps that have none (RFC 3164); default: the year each file was last modified
+    #[arg(long)]
+    syslog_year: Option<u16>,
+    /// Append lines that do not start a new entry (stack traces) to the previous entry
+    #[arg(long)]
+    multiline: bool,
+    /// Regex marking lines that start a new entry; implies --multiline
+    #[arg(long)]
+    entry_start: Option<String>,
+    /// Number of example lines printed for each kind of parse error
+    #[arg(long, default_value_t = 3)]
+    max_errors_per_kind: usize,
+    /// Descend into subdirectories of directory arguments
+    #[arg(short, long)]
+    recursive: bool,
+    /// Maximum number of directory levels below each directory argument
+    #[arg(long)]
+    max_depth: Option<usize>,
+    /// Glob for files to read, relative to the directory (repeatable; default *.log and *.log.*)
+    #[arg(long)]
+    include: Vec<String>,
+    /// Glob for files to leave out (repeatable)
+    #[arg(long)]
+    exclude: Vec<String>,
+    /// Follow symbolic links to directories as well as files
+    #[arg(long, conflicts_with = "no_symlinks")]
+    follow_symlinks: bool,
+    /// Skip symbolic links instead of reading the files they point to
+    #[arg(long)]
+    no_symlinks: bool,
+    /// Merge all inputs into one timestamp-ordered stream, reading rotated files oldest first
+    #[arg(long)]
+    merge: bool,
+    /// Keep entries at this level and above
+    #[arg(long)]
+    level: Option<LogLevel>,
+    /// Keep entries at this level and below
+    #[arg(long)]
+    max_level: Option<LogLevel>,
+    /// Keep only these components (repeatable)
+    #[arg(long)]
+    component: Vec<String>,
+    /// Leave out these components (repeatable)
+    #[arg(long)]
+    exclude_component: Vec<String>,
+    /// Keep entries at or after this time, e.g. "2024-01-15 10:00:00"
+    #[arg(long)]
+    since: Option<DateTime>,
+    /// Keep entries before this time
+    #[arg(long)]
+    until: Option<DateTime>,
+    /// Keep entries whose message contains this text (repeatable)
+    #[arg(long)]
+    grep: Vec<String>,
+    /// Keep entries whose message matches this regex (repeatable)
+    #[arg(long)]
+    grep_regex: Vec<String>,
+    /// Add a timeline to the report with buckets of this width: second, minute, hour, day, 30s, 5m, ...
+    #[arg(long)]
+    timeline: Option<BucketWidth>,
+    /// Group messages into templates (numbers, paths, IPs, ids masked) and report the most frequent
+    #[arg(long)]
+    templates: bool,
+    /// Report time buckets whose volume or error count is far from the rolling baseline
+    /// (uses the --timeline width, or one-minute buckets)
+    #[arg(long)]
+    anomalies: bool,
+    /// Robust z-score from which a bucket counts as an anomaly
+    #[arg(long, default_value_t = 3.5)]
+    anomaly_threshold: f64,
+    /// Number of preceding buckets the baseline is computed from
+    #[arg(long, default_value_t = 30)]
+    anomaly_window: usize,
+    /// Report stretches in which a component logged nothing
+    #[arg(long)]
+    gaps: bool,
+    /// Silences at least this long are gaps, e.g. 90s, 5m, 1h
+    #[arg(long, default_value = "5m", value_parser = parse_duration)]
+    gap_threshold: Duration,
+    /// So are silences this many times longer than the component's usual interval
+    #[arg(long, default_value_t = 10.0)]
+    gap_factor: f64,
+    /// Run a query over the entries instead of printing the report, e.g.
+    /// "SELECT component, count() WHERE level >= warn GROUP BY component ORDER BY count DESC"
+    #[arg(long)]
+    query: Option<String>,
+    /// Only aggregate: entries are counted and dropped instead of kept in memory
+    #[arg(long)]
+    stats_only: bool,
+    /// Number of parse errors kept for the diagnostics; the rest are only counted
+    /// (default: all, or 1000 with --stats-only)
+    #[arg(long)]
+    max_parse_errors: Option<usize>,
+    /// Files parsed at once when reading a directory (default: number of CPUs)
+    #[arg(short = 'j', long)]
+    workers: Option<usize>,
+    /// Compare the inputs against these baseline files or directories (repeatable)
+    /// instead of printing the report
+    #[arg(long)]
+    baseline: Vec<PathBuf>,
+    /// Compare the inputs against statistics saved earlier with --save-snapshot
+    #[arg(long)]
+    baseline_snapshot: Option<PathBuf>,
+    /// Also save the statistics to this file, e.g. to use later as a --baseline-snapshot
+    #[arg(long)]
+    save_snapshot: Option<PathBuf>,
+    /// Compare this snapshot against --baseline-snapshot instead of reading log files
+    #[arg(long)]
+    snapshot: Option<PathBuf>,
+    /// Keep reading appended lines like `tail -F` and refresh the report every --interval seconds (implies --stats-only)
+    #[arg(short = 'F', long)]
+    follow: bool,
+    /// Seconds between refreshes with --follow
+    #[arg(long, default_value_t = 2.0)]
+    interval: f64,
 }
 
-fn main() {
+fn main() -> ExitCode {
  let cli = Cli::parse();
 
+    if let Some(snapshot) = &cli.snapshot {
+        return compare_snapshots(snapshot, &cli);
+    }
+
     if cli.paths.is_empty() {
         println!("No File or Directory provided");
-        return;
+        return ExitCode::FAILURE;
+    }
+
+    let query = match cli.query.as_deref().map(|q| (q, q.parse::<Query>())) {
+        None => None,
+        Some((_, Ok(query))) => Some(query),
+        Some((text, Err(e))) => {
+            eprint!("{}", e.render(text));
+            return ExitCode::FAILURE;
+        }
+    };
+    if query.is_some() && cli.stats_only {
+        println!("--query needs the parsed entries and cannot be combined with --stats-only");
+        return ExitCode::FAILURE;
+    }
+    if cli.follow && (query.is_some() || cli.save_snapshot.is_some()) {
+        println!("--follow cannot be combined with --query or --save-snapshot");
+        return ExitCode::FAILURE;
+    }
+    let comparing = !cli.baseline.is_empty() || cli.baseline_snapshot.is_some();
+    if comparing && (query.is_some() || cli.follow) {
+        println!("--baseline cannot be combined with --query or --follow");
+        return ExitCode::FAILURE;
+    }
+    if !cli.baseline.is_empty() && cli.baseline_snapshot.is_some() {
+        println!("Use either --baseline or --baseline-snapshot, not both");
+        return ExitCode::FAILURE;
     }
 
-    let mut analyzer = LogAnalyzer::new();
+    let mut analyzer = match build_analyzer(&cli) {
+        Ok(analyzer) => analyzer,
+        Err(e) => {
+            println!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
 
-    for path in &cli.paths {
-        let result = if path.is_file() {
-            analyzer.process_file(path)
-        } else if path.is_dir() {
-            analyzer.process_directory(path)
-        } else {
-            println!("Invalid path: {:?}", path);
-            continue;
+    if cli.follow {
+        let interval = match Duration::try_from_secs_f64(cli.interval.max(0.1)) {
+            Ok(interval) => interval,
+            Err(_) => {
+                println!("Invalid --interval {}: expected a number of seconds", cli.interval);
+                return ExitCode::FAILURE;
+            }
         };
+        // A line still being written gets several refreshes to be finished
+        analyzer = analyzer.with_partial_line_grace(PARTIAL_LINE_GRACE.max(interval.saturating_mul(10)));
+        return follow(&mut analyzer, &cli, interval);
+    }
+
+    // Collect templates whenever the statistics may be compared, so new and gone ones can be listed
+    if comparing || cli.save_snapshot.is_some() {
+        analyzer = analyzer.with_templates();
+    }
+
+    if comparing {
+        let before = match &cli.baseline_snapshot {
+            Some(path) => match load_snapshot(path) {
+                Ok(stats) => stats,
+                Err(e) => {
+                    println!("{}", e);
+                    return ExitCode::FAILURE;
+                }
+            },
+            None => {
+                let mut baseline = match build_analyzer(&cli) {
+                    Ok(baseline) => baseline.with_templates(),
+                    Err(e) => {
+                        println!("{}", e);
+                        return ExitCode::FAILURE;
+                    }
+                };
+                if !process_paths(&mut baseline, &cli.baseline, cli.merge) {
+                    return ExitCode::FAILURE;
+                }
+                baseline.statistics()
+            }
+        };
+        if !process_paths(&mut analyzer, &cli.paths, cli.merge) {
+            return ExitCode::FAILURE;
+        }
+        save_statistics(analyzer.statistics_ref(), &cli);
+
+        print_comparison(&before, analyzer.statistics_ref(), &cli);
+        return ExitCode::SUCCESS;
+    }
+
+    // Every input failed (and said why): there is nothing to report
+    if !process_paths(&mut analyzer, &cli.paths, cli.merge) {
+        return ExitCode::FAILURE;
+    }
+    save_statistics(analyzer.statistics_ref(), &cli);
+
+    let stats = analyzer.statistics_ref();
+    let error_count = analyzer.parse_error_count();
 
-        if let Err(e) = result {
-            println!("Error processing {:?}: {:?}", path, e);
+    if error_count > 0 {
+        eprint!("{}", render_diagnostics(analyzer.parse_errors(), cli.max_errors_per_kind));
+        let not_kept = error_count - analyzer.parse_errors().len();
+        if not_kept > 0 {
+            eprintln!("  ({} more parse errors were counted but not kept)", not_kept);
         }
     }
 
-    let stats = analyzer.statistics();
-    let error_count = analyzer.parse_errors().len();
+    if let Some(query) = &query {
+        let result = query.run(analyzer.entries());
+        if cli.format == "json" {
+            println!("{}", serde_json::to_string_pretty(&result.to_json()).unwrap());
+        } else {
+            print!("{}", result.render_table());
+        }
+        return ExitCode::SUCCESS;
+    }
 
-    println!("Errors in files: {:?}", analyzer.parse_errors());
+    let anomalies = find_anomalies(stats, &cli);
+    let gaps = find_component_gaps(stats, &cli);
+    let ctx = ReportContext {
+        parse_error_count: error_count,
+        filtered_count: analyzer.filtered_count(),
+        detected_formats: analyzer.detected_formats(),
+        skipped_files: analyzer.skipped_files(),
+        anomalies: anomalies.as_deref(),
+        gaps: gaps.as_deref(),
+    };
+
+    if cli.format == "json" {
+        print_report_json(stats, &ctx);
+    } else {
+        print_report(stats, &ctx);
+    }
+    ExitCode::SUCCESS
+}
 
+fn print_comparison(before: &Statistics, after: &Statistics, cli: &Cli) {
+    let diff = compare(before, after, &DiffConfig::default());
     if cli.format == "json" {
-        print_report_json(&stats, error_count);
+        print_diff_json(&diff);
     } else {
-        print_report(&stats, error_count);
+        print_diff(&diff);
+    }
+}
+
+// Compares two saved snapshots without reading any logs.
+fn compare_snapshots(snapshot: &Path, cli: &Cli) -> ExitCode {
+    let Some(baseline) = &cli.baseline_snapshot else {
+        println!("--snapshot needs a --baseline-snapshot to compare against");
+        return ExitCode::FAILURE;
+    };
+    if !cli.paths.is_empty() || !cli.baseline.is_empty() || cli.query.is_some() || cli.follow {
+        println!("--snapshot compares saved statistics and takes no log files, --baseline, --query or --follow");
+        return ExitCode::FAILURE;
+    }
+    let (before, after) = match (load_snapshot(baseline), load_snapshot(snapshot)) {
+        (Ok(before), Ok(after)) => (before, after),
+        (Err(e), _) | (_, Err(e)) => {
+            println!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    print_comparison(&before, &after, cli);
+    ExitCode::SUCCESS
+}
+
+fn save_statistics(stats: &Statistics, cli: &Cli) {
+    if let Some(path) = &cli.save_snapshot
+        && let Err(e) = save_snapshot(stats, path)
+    {
+        println!("{}", e);
+    }
+}
+
+// The analyzer as configured by the command line options.
+fn build_analyzer(cli: &Cli) -> Result<LogAnalyzer, String> {
+    let syslog = cli.syslog_year.map(SyslogFormat::with_year).unwrap_or_default();
+    let formats = builtin_formats_with(syslog);
+    let mut analyzer = if cli.input_format == "auto" {
+        LogAnalyzer::new().with_format_detection(FormatDetector::new(formats))
+    } else {
+        let Some(input_format) = formats.into_iter().find(|f| f.name().eq_ignore_ascii_case(&cli.input_format)) else {
+            return Err(format!("Unknown input format: {}", cli.input_format));
+        };
+        LogAnalyzer::new().with_format(input_format)
+    };
+
+    let mut walk = WalkOptions {
+        recursive: cli.recursive,
+        max_depth: cli.max_depth,
+        exclude: cli.exclude.clone(),
+        symlinks: if cli.follow_symlinks {
+            SymlinkPolicy::All
+        } else if cli.no_symlinks {
+            SymlinkPolicy::Skip
+        } else {
+            SymlinkPolicy::Files
+        },
+        ..WalkOptions::default()
+    };
+    if !cli.include.is_empty() {
+        walk.include = cli.include.clone();
+    }
+    analyzer = analyzer.with_walk_options(walk);
+
+    let mut filter = EntryFilter {
+        min_level: cli.level,
+        max_level: cli.max_level,
+        components: cli.component.clone(),
+        exclude_components: cli.exclude_component.clone(),
+        since: cli.since.clone(),
+        until: cli.until.clone(),
+        messages: cli.grep.iter().map(|text| MessageMatch::Contains(text.clone())).collect(),
+    };
+    for pattern in &cli.grep_regex {
+        match regex::Regex::new(pattern) {
+            Ok(re) => filter.messages.push(MessageMatch::Regex(re)),
+            Err(e) => return Err(format!("Invalid --grep-regex pattern: {}", e)),
+        }
+    }
+    analyzer = analyzer.with_filter(filter);
+
+    if let Some(width) = cli.timeline.or(cli.anomalies.then_some(BucketWidth::MINUTE)) {
+        analyzer = analyzer.with_timeline(width);
+    }
+    if cli.templates {
+        analyzer = analyzer.with_templates();
+    }
+    if cli.gaps {
+        analyzer = analyzer.with_activity();
+    }
+
+    // Following runs indefinitely, so it only keeps the statistics
+    let stats_only = cli.stats_only || cli.follow;
+    analyzer = analyzer.with_statistics_only(stats_only);
+    if let Some(max) = cli.max_parse_errors.or(stats_only.then_some(1000)) {
+        analyzer = analyzer.with_max_parse_errors(max);
+    }
+
+    let workers = cli.workers.unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+    analyzer = analyzer.with_workers(workers);
+
+    if let Some(pattern) = &cli.entry_start {
+        match regex::Regex::new(pattern) {
+            Ok(re) => analyzer = analyzer.with_multiline(EntryStart::Pattern(re)),
+            Err(e) => return Err(format!("Invalid --entry-start pattern: {}", e)),
+        }
+    } else if cli.multiline {
+        analyzer = analyzer.with_multiline(EntryStart::Parseable);
+    }
+
+    Ok(analyzer)
+}
+
+// Reads every path into the analyzer, reporting failures as it goes.
+// Reads every path, printing the error of those that fail. Returns false if none could be read.
+fn process_paths(analyzer: &mut LogAnalyzer, paths: &[PathBuf], merge: bool) -> bool {
+    if merge {
+        let mut files = Vec::new();
+        for path in paths {
+            if path.is_dir() {
+                match analyzer.collect_directory(path) {
+                    Ok(found) => files.extend(found),
+                    Err(e) => println!("Error processing {}: {}", path.display(), e),
+                }
+            } else {
+                // Files, named pipes and `-` for stdin; anything else fails with a reason
+                files.push(path.clone());
+            }
+        }
+        if let Err(e) = analyzer.process_files_merged(&files) {
+            println!("Error merging files: {}", e);
+            return false;
+        }
+        !files.is_empty()
+    } else {
+        let mut any_read = false;
+        for path in paths {
+            let result = if path.is_dir() {
+                analyzer.process_directory(path)
+            } else {
+                // Files, named pipes and `-` for stdin; anything else fails with a reason
+                analyzer.process_file(path)
+            };
+
+            match result {
+                Ok(_) => any_read = true,
+                Err(e) => println!("Error processing {}: {}", path.display(), e),
+            }
+        }
+        any_read
+    }
+}
+
+fn find_anomalies(stats: &Statistics, cli: &Cli) -> Option<Vec<Anomaly>> {
+    if !cli.anomalies {
+        return None;
+    }
+    let config = AnomalyConfig {
+        threshold: cli.anomaly_threshold,
+        window: cli.anomaly_window,
+        ..AnomalyConfig::default()
+    };
+    stats.timeline.as_ref().map(|timeline| detect_anomalies(timeline, &config))
+}
+
+fn find_component_gaps(stats: &Statistics, cli: &Cli) -> Option<Vec<Gap>> {
+    let config = GapConfig {
+        threshold: cli.gap_threshold,
+        factor: cli.gap_factor,
+        ..GapConfig::default()
+    };
+    stats.activity.as_ref().map(|activity| find_gaps(activity, &config, stats.last_entry.as_ref()))
+}
+
+// Polls the inputs forever, redrawing the text report or printing one JSON line per interval.
+fn follow(analyzer: &mut LogAnalyzer, cli: &Cli, interval: Duration) -> ExitCode {
+    for path in &cli.paths {
+        let files = if path.is_dir() {
+            match analyzer.collect_directory(path) {
+                Ok(files) => files,
+                Err(e) => {
+                    println!("Error processing {}: {}", path.display(), e);
+                    continue;
+                }
+            }
+        } else {
+            vec![path.clone()]
+        };
+
+        for file in files {
+            if let Err(e) = analyzer.follow(&file) {
+                println!("Error processing {}: {}", file.display(), e);
+            }
+        }
+    }
+
+    loop {
+        if analyzer.followed_count() == 0 {
+            println!("No files left to follow");
+            return ExitCode::FAILURE;
+        }
+        let outcome = analyzer.poll();
+        for e in &outcome.errors {
+            eprintln!("Error following files: {}", e);
+        }
+        for path in &outcome.stopped {
+            eprintln!("Stopped following {} after {} failed reads", path.display(), MAX_FOLLOW_FAILURES);
+        }
+
+        let anomalies = find_anomalies(analyzer.statistics_ref(), cli);
+        let gaps = find_component_gaps(analyzer.statistics_ref(), cli);
+        let ctx = ReportContext {
+            parse_error_count: analyzer.parse_error_count(),
+            filtered_count: analyzer.filtered_count(),
+            detected_formats: analyzer.detected_formats(),
+            skipped_files: analyzer.skipped_files(),
+            anomalies: anomalies.as_deref(),
+            gaps: gaps.as_deref(),
+        };
+
+        if cli.format == "json" {
+            print_report_json_line(analyzer.statistics_ref(), &ctx);
+        } else {
+            // Clear the screen and redraw from the top
+            print!("\x1b[2J\x1b[H");
+            print_report(analyzer.statistics_ref(), &ctx);
+        }
+
+        std::thread::sleep(interval);
     }
 }
@@ -0,0 +1,40 @@
+use std::path::Path;
+
+use regex::Regex;
+
+use crate::log_entry::{LogEntry, ParseError};
+use crate::log_format::LogFormat;
+
+// Rule deciding which lines start a new entry when multi-line entries are enabled.
+// Every other line (a stack trace frame, a wrapped message) is appended to the
+// message of the entry before it.
+#[derive(Debug, Clone)]
+pub enum EntryStart {
+    // A line starts a new entry if the format can parse it.
+    Parseable,
+    // A line starts a new entry if it matches the pattern; it is then parsed
+    // normally and reported as a ParseError if that fails.
+    Pattern(Regex),
+}
+
+impl EntryStart {
+    // Lines starting with a YYYY-MM-DD date, as in the bracketed format.
+    pub fn date_prefix() -> Self {
+        EntryStart::Pattern(Regex::new(r"^\s*\d{4}-\d{2}-\d{2}").expect("valid date prefix pattern"))
+    }
+
+    // Parses `line` if it starts a new entry; None means it is a continuation line.
+    pub(crate) fn parse_start(
+        &self,
+        format: &dyn LogFormat,
+        line: &str,
+        source_file: &Path,
+        line_number: usize,
+    ) -> Option<Result<LogEntry, ParseError>> {
+        match self {
+            EntryStart::Parseable => format.parse_line(line, source_file, line_number).ok().map(Ok),
+            EntryStart::Pattern(re) if re.is_match(line) => Some(format.parse_line(line, source_file, line_number)),
+            EntryStart::Pattern(_) => None,
+        }
+    }
+}
@@ -0,0 +1,887 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+use std::str::FromStr;
+
+use regex::Regex;
+use thiserror::Error;
+
+use crate::date_time::DateTime;
+use crate::log_entry::LogEntry;
+use crate::log_level::LogLevel;
+
+// A small SQL-like query over parsed entries:
+//
+//   SELECT component, level, count() WHERE level >= warn
+//   GROUP BY component, level ORDER BY count DESC LIMIT 10
+//
+// Fields: timestamp, hour (timestamp truncated to the UTC hour), level, component,
+// message, source and fields.<key> for extra fields. Aggregates: count(), min(f), max(f).
+// WHERE supports = != < <= > >=, CONTAINS, MATCHES (regex), IN (...), AND, OR, NOT.
+// Keywords are case-insensitive; bare words on the right-hand side are values.
+
+#[derive(Debug, Clone, PartialEq, Error)]
+#[error("{message} (at column {column})")]
+pub struct QueryError {
+    pub message: String,
+    pub position: usize, // byte offset into the query
+    pub column: usize,   // 1-based character column, for display
+}
+
+impl QueryError {
+    fn new(query: &str, position: usize, message: impl Into<String>) -> Self {
+        let position = position.min(query.len());
+        QueryError {
+            message: message.into(),
+            position,
+            column: query[..position].chars().count() + 1,
+        }
+    }
+
+    // The query with a caret under the error position.
+    pub fn render(&self, query: &str) -> String {
+        format!("error: {}\n  | {}\n  | {}^\n", self.message, query, " ".repeat(self.column - 1))
+    }
+}
+
+// ---------------- values ----------------
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Value {
+    Null,
+    Number(u64),
+    Level(LogLevel),
+    Time(DateTime),
+    Text(String),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "-"),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Level(level) => write!(f, "{}", level),
+            Value::Time(time) => write!(f, "{}", time),
+            Value::Text(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+impl Value {
+    fn to_json(&self) -> serde_json::Value {
+        match self {
+            Value::Null => serde_json::Value::Null,
+            Value::Number(n) => serde_json::Value::from(*n),
+            other => serde_json::Value::String(other.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Type {
+    Time,
+    Level,
+    Text,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Time => write!(f, "a timestamp"),
+            Type::Level => write!(f, "a log level"),
+            Type::Text => write!(f, "text"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Field {
+    Timestamp,
+    Hour,
+    Level,
+    Component,
+    Message,
+    Source,
+    Extra(String),
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Field> {
+        match name.to_ascii_lowercase().as_str() {
+            "timestamp" | "time" => Some(Field::Timestamp),
+            "hour" => Some(Field::Hour),
+            "level" => Some(Field::Level),
+            "component" => Some(Field::Component),
+            "message" | "msg" => Some(Field::Message),
+            "source" | "file" => Some(Field::Source),
+            _ => name
+                .strip_prefix("fields.")
+                .filter(|key| !key.is_empty())
+                .map(|key| Field::Extra(key.to_string())),
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Field::Timestamp => "timestamp".to_string(),
+            Field::Hour => "hour".to_string(),
+            Field::Level => "level".to_string(),
+            Field::Component => "component".to_string(),
+            Field::Message => "message".to_string(),
+            Field::Source => "source".to_string(),
+            Field::Extra(key) => format!("fields.{}", key),
+        }
+    }
+
+    fn value_type(&self) -> Type {
+        match self {
+            Field::Timestamp | Field::Hour => Type::Time,
+            Field::Level => Type::Level,
+            _ => Type::Text,
+        }
+    }
+
+    fn value(&self, entry: &LogEntry) -> Value {
+        match self {
+            Field::Timestamp => Value::Time(entry.timestamp.clone()),
+            Field::Hour => {
+                let utc = entry.timestamp.to_utc();
+                Value::Time(DateTime { minute: 0, second: 0, nanosecond: 0, ..utc })
+            }
+            Field::Level => Value::Level(entry.level),
+            Field::Component => Value::Text(entry.component.clone()),
+            Field::Message => Value::Text(entry.message.clone()),
+            Field::Source => Value::Text(entry.source_file.display().to_string()),
+            Field::Extra(key) => entry.fields.get(key).map_or(Value::Null, |v| Value::Text(v.clone())),
+        }
+    }
+}
+
+// ---------------- lexer ----------------
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String), // keyword, field name or bare value
+    Str(String),
+    Number(u64),
+    Comma,
+    LParen,
+    RParen,
+    Star,
+    Op(CmpOp),
+    End,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CmpOp {
+    fn holds(self, ordering: std::cmp::Ordering) -> bool {
+        use std::cmp::Ordering::*;
+        match self {
+            CmpOp::Eq => ordering == Equal,
+            CmpOp::Ne => ordering != Equal,
+            CmpOp::Lt => ordering == Less,
+            CmpOp::Le => ordering != Greater,
+            CmpOp::Gt => ordering == Greater,
+            CmpOp::Ge => ordering != Less,
+        }
+    }
+}
+
+fn tokenize(query: &str) -> Result<Vec<(Token, usize)>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut chars = query.char_indices().peekable();
+
+    while let Some(&(pos, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let token = match c {
+            ',' => Token::Comma,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '*' => Token::Star,
+            '=' => Token::Op(CmpOp::Eq),
+            '!' | '<' | '>' => {
+                chars.next();
+                let next = chars.peek().map(|&(_, c)| c);
+                let (op, two) = match (c, next) {
+                    ('!', Some('=')) => (CmpOp::Ne, true),
+                    ('<', Some('>')) => (CmpOp::Ne, true),
+                    ('<', Some('=')) => (CmpOp::Le, true),
+                    ('>', Some('=')) => (CmpOp::Ge, true),
+                    ('<', _) => (CmpOp::Lt, false),
+                    ('>', _) => (CmpOp::Gt, false),
+                    _ => return Err(QueryError::new(query, pos, "expected '!='")),
+                };
+                if two {
+                    chars.next();
+                }
+                tokens.push((Token::Op(op), pos));
+                continue;
+            }
+            '\'' | '"' => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, q)) if q == c => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, escaped)) => text.push(escaped),
+                            None => return Err(QueryError::new(query, pos, "unterminated string")),
+                        },
+                        Some((_, other)) => text.push(other),
+                        None => return Err(QueryError::new(query, pos, "unterminated string")),
+                    }
+                }
+                tokens.push((Token::Str(text), pos));
+                continue;
+            }
+            c if c.is_ascii_digit() || c.is_alphabetic() || c == '_' => {
+                let mut word = String::new();
+                while let Some(&(_, c)) = chars.peek() {
+                    // Words may contain '.', ':' and '-' so that fields.user and
+                    // bare timestamps such as 2024-01-15T10:00:00Z need no quotes
+                    if c.is_alphanumeric() || matches!(c, '_' | '.' | ':' | '-' | '+') {
+                        word.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let token = match word.parse::<u64>() {
+                    Ok(n) => Token::Number(n),
+                    Err(_) => Token::Word(word),
+                };
+                tokens.push((token, pos));
+                continue;
+            }
+            other => return Err(QueryError::new(query, pos, format!("unexpected character '{}'", other))),
+        };
+        chars.next();
+        tokens.push((token, pos));
+    }
+
+    tokens.push((Token::End, query.len()));
+    Ok(tokens)
+}
+
+// ---------------- syntax ----------------
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Aggregate {
+    Count,
+    Min,
+    Max,
+}
+
+#[derive(Debug, Clone)]
+enum Column {
+    Field(Field),
+    Aggregate(Aggregate, Option<Field>), // count() has no field
+}
+
+#[derive(Debug, Clone)]
+struct SelectItem {
+    column: Column,
+    name: String,
+    position: usize,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Field, CmpOp, Value),
+    Contains(Field, String),
+    Matches(Field, Regex),
+    In(Field, Vec<Value>),
+}
+
+impl Expr {
+    fn eval(&self, entry: &LogEntry) -> bool {
+        match self {
+            Expr::And(a, b) => a.eval(entry) && b.eval(entry),
+            Expr::Or(a, b) => a.eval(entry) || b.eval(entry),
+            Expr::Not(e) => !e.eval(entry),
+            Expr::Compare(field, op, value) => match field.value(entry) {
+                Value::Null => false,
+                actual => op.holds(actual.cmp(value)),
+            },
+            Expr::Contains(field, text) => match field.value(entry) {
+                Value::Text(actual) => actual.contains(text.as_str()),
+                _ => false,
+            },
+            Expr::Matches(field, re) => match field.value(entry) {
+                Value::Text(actual) => re.is_match(&actual),
+                _ => false,
+            },
+            Expr::In(field, values) => values.contains(&field.value(entry)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Query {
+    select: Vec<SelectItem>,
+    filter: Option<Expr>,
+    group_by: Vec<Field>,
+    order_by: Vec<(usize, bool)>, // column index, descending
+    limit: Option<usize>,
+}
+
+struct Parser<'a> {
+    query: &'a str,
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].0
+    }
+
+    fn position(&self) -> usize {
+        self.tokens[self.pos].1
+    }
+
+    fn next(&mut self) -> (Token, usize) {
+        let token = self.tokens[self.pos].clone();
+        if self.pos + 1 < self.tokens.len() {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn error(&self, message: impl Into<String>) -> QueryError {
+        QueryError::new(self.query, self.position(), message)
+    }
+
+    fn describe(token: &Token) -> String {
+        match token {
+            Token::Word(w) => format!("'{}'", w),
+            Token::Str(s) => format!("string '{}'", s),
+            Token::Number(n) => format!("'{}'", n),
+            Token::Comma => "','".to_string(),
+            Token::LParen => "'('".to_string(),
+            Token::RParen => "')'".to_string(),
+            Token::Star => "'*'".to_string(),
+            Token::Op(_) => "operator".to_string(),
+            Token::End => "end of query".to_string(),
+        }
+    }
+
+    fn at_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Token::Word(w) if w.eq_ignore_ascii_case(keyword))
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.at_keyword(keyword);
+        if found {
+            self.next();
+        }
+        found
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), QueryError> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected {}, found {}", keyword, Self::describe(self.peek()))))
+        }
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), QueryError> {
+        if *self.peek() == token {
+            self.next();
+            Ok(())
+        } else {
+            Err(self.error(format!("expected {}, found {}", Self::describe(&token), Self::describe(self.peek()))))
+        }
+    }
+
+    fn field(&mut self) -> Result<(Field, usize), QueryError> {
+        let position = self.position();
+        match self.next().0 {
+            Token::Word(name) => Field::from_name(&name)
+                .map(|field| (field, position))
+                .ok_or_else(|| QueryError::new(self.query, position, format!("unknown field '{}'", name))),
+            other => Err(QueryError::new(
+                self.query,
+                position,
+                format!("expected a field name, found {}", Self::describe(&other)),
+            )),
+        }
+    }
+
+    fn aggregate_name(word: &str) -> Option<Aggregate> {
+        match word.to_ascii_lowercase().as_str() {
+            "count" => Some(Aggregate::Count),
+            "min" => Some(Aggregate::Min),
+            "max" => Some(Aggregate::Max),
+            _ => None,
+        }
+    }
+
+    // A field, or an aggregate call such as count() or max(timestamp).
+    fn column(&mut self) -> Result<(Column, String, usize), QueryError> {
+        let position = self.position();
+        let aggregate = match (self.peek(), self.tokens.get(self.pos + 1).map(|t| &t.0)) {
+            (Token::Word(w), Some(Token::LParen)) => Self::aggregate_name(w),
+            _ => None,
+        };
+
+        let Some(aggregate) = aggregate else {
+            if let (Token::Word(w), Some(Token::LParen)) = (self.peek(), self.tokens.get(self.pos + 1).map(|t| &t.0)) {
+                return Err(self.error(format!("unknown function '{}'", w)));
+            }
+            let (field, _) = self.field()?;
+            let name = field.name();
+            return Ok((Column::Field(field), name, position));
+        };
+
+        self.next();
+        self.expect(Token::LParen)?;
+        let field = match (aggregate, self.peek()) {
+            (Aggregate::Count, Token::RParen) => None,
+            (Aggregate::Count, Token::Star) => {
+                self.next();
+                None
+            }
+            _ => Some(self.field()?.0),
+        };
+        self.expect(Token::RParen)?;
+
+        let name = match (&aggregate, &field) {
+            (Aggregate::Count, None) => "count".to_string(),
+            (Aggregate::Count, Some(f)) => format!("count({})", f.name()),
+            (Aggregate::Min, Some(f)) => format!("min({})", f.name()),
+            (Aggregate::Max, Some(f)) => format!("max({})", f.name()),
+            (_, None) => unreachable!("min and max always take a field"),
+        };
+        Ok((Column::Aggregate(aggregate, field), name, position))
+    }
+
+    fn query(&mut self) -> Result<Query, QueryError> {
+        self.expect_keyword("SELECT")?;
+
+        let mut select = Vec::new();
+        loop {
+            let (column, mut name, position) = self.column()?;
+            if self.eat_keyword("AS") {
+                match self.next() {
+                    (Token::Word(alias), _) | (Token::Str(alias), _) => name = alias,
+                    (other, pos) => {
+                        return Err(QueryError::new(
+                            self.query,
+                            pos,
+                            format!("expected a column name after AS, found {}", Self::describe(&other)),
+                        ));
+                    }
+                }
+            }
+            select.push(SelectItem { column, name, position });
+            if *self.peek() != Token::Comma {
+                break;
+            }
+            self.next();
+        }
+
+        let filter = if self.eat_keyword("WHERE") { Some(self.or_expr()?) } else { None };
+
+        let mut group_by = Vec::new();
+        if self.eat_keyword("GROUP") {
+            self.expect_keyword("BY")?;
+            loop {
+                group_by.push(self.field()?.0);
+                if *self.peek() != Token::Comma {
+                    break;
+                }
+                self.next();
+            }
+        }
+
+        let mut order_by = Vec::new();
+        if self.eat_keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            loop {
+                // A column name or alias (count, first), or the column itself (count(), msg)
+                let position = self.position();
+                let names = match self.peek() {
+                    Token::Word(w) if self.tokens.get(self.pos + 1).map(|t| &t.0) != Some(&Token::LParen) => {
+                        let w = w.clone();
+                        self.next();
+                        let canonical = Field::from_name(&w).map(|f| f.name());
+                        vec![Some(w), canonical]
+                    }
+                    _ => vec![Some(self.column()?.1)],
+                };
+                let index = names
+                    .iter()
+                    .flatten()
+                    .find_map(|name| select.iter().position(|item| item.name.eq_ignore_ascii_case(name)))
+                    .ok_or_else(|| QueryError::new(self.query, position, "ORDER BY must name a selected column"))?;
+                let descending = if self.eat_keyword("DESC") {
+                    true
+                } else {
+                    self.eat_keyword("ASC");
+                    false
+                };
+                order_by.push((index, descending));
+                if *self.peek() != Token::Comma {
+                    break;
+                }
+                self.next();
+            }
+        }
+
+        let limit = if self.eat_keyword("LIMIT") {
+            match self.next() {
+                (Token::Number(n), _) => Some(n as usize),
+                (other, pos) => {
+                    return Err(QueryError::new(
+                        self.query,
+                        pos,
+                        format!("expected a number after LIMIT, found {}", Self::describe(&other)),
+                    ));
+                }
+            }
+        } else {
+            None
+        };
+
+        if *self.peek() != Token::End {
+            return Err(self.error(format!("unexpected {}", Self::describe(self.peek()))));
+        }
+
+        let query = Query { select, filter, group_by, order_by, limit };
+        // A plain field next to aggregates must be one of the groups
+        if query.is_grouped() {
+            for item in &query.select {
+                if let Column::Field(field) = &item.column
+                    && !query.group_by.contains(field)
+                {
+                    return Err(QueryError::new(
+                        self.query,
+                        item.position,
+                        format!("'{}' must appear in GROUP BY or inside an aggregate", field.name()),
+                    ));
+                }
+            }
+        }
+        Ok(query)
+    }
+
+    fn or_expr(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.and_expr()?;
+        while self.eat_keyword("OR") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and_expr()?));
+        }
+        Ok(expr)
+    }
+
+    fn and_expr(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.not_expr()?;
+        while self.eat_keyword("AND") {
+            expr = Expr::And(Box::new(expr), Box::new(self.not_expr()?));
+        }
+        Ok(expr)
+    }
+
+    fn not_expr(&mut self) -> Result<Expr, QueryError> {
+        if self.eat_keyword("NOT") {
+            return Ok(Expr::Not(Box::new(self.not_expr()?)));
+        }
+        if *self.peek() == Token::LParen {
+            self.next();
+            let expr = self.or_expr()?;
+            self.expect(Token::RParen)?;
+            return Ok(expr);
+        }
+        self.condition()
+    }
+
+    fn condition(&mut self) -> Result<Expr, QueryError> {
+        let (field, _) = self.field()?;
+
+        if self.eat_keyword("CONTAINS") {
+            self.expect_text_field(&field, "CONTAINS")?;
+            let (text, _) = self.literal_text()?;
+            return Ok(Expr::Contains(field, text));
+        }
+        if self.eat_keyword("MATCHES") {
+            self.expect_text_field(&field, "MATCHES")?;
+            let (pattern, position) = self.literal_text()?;
+            let re = Regex::new(&pattern)
+                .map_err(|e| QueryError::new(self.query, position, format!("invalid regex: {}", e)))?;
+            return Ok(Expr::Matches(field, re));
+        }
+        if self.eat_keyword("IN") {
+            self.expect(Token::LParen)?;
+            let mut values = Vec::new();
+            loop {
+                values.push(self.value_for(&field)?);
+                if *self.peek() != Token::Comma {
+                    break;
+                }
+                self.next();
+            }
+            self.expect(Token::RParen)?;
+            return Ok(Expr::In(field, values));
+        }
+
+        let op = match self.next() {
+            (Token::Op(op), _) => op,
+            (other, pos) => {
+                return Err(QueryError::new(
+                    self.query,
+                    pos,
+                    format!("expected a comparison after '{}', found {}", field.name(), Self::describe(&other)),
+                ));
+            }
+        };
+        let value = self.value_for(&field)?;
+        Ok(Expr::Compare(field, op, value))
+    }
+
+    fn expect_text_field(&self, field: &Field, operator: &str) -> Result<(), QueryError> {
+        match field.value_type() {
+            Type::Text => Ok(()),
+            other => Err(QueryError::new(
+                self.query,
+                self.tokens[self.pos - 1].1,
+                format!("{} needs a text field, but '{}' is {}", operator, field.name(), other),
+            )),
+        }
+    }
+
+    fn literal_text(&mut self) -> Result<(String, usize), QueryError> {
+        match self.next() {
+            (Token::Str(s), pos) | (Token::Word(s), pos) => Ok((s, pos)),
+            (Token::Number(n), pos) => Ok((n.to_string(), pos)),
+            (other, pos) => Err(QueryError::new(
+                self.query,
+                pos,
+                format!("expected a value, found {}", Self::describe(&other)),
+            )),
+        }
+    }
+
+    // A literal, converted to the type of `field` so it can be compared directly.
+    fn value_for(&mut self, field: &Field) -> Result<Value, QueryError> {
+        let (text, position) = self.literal_text()?;
+        let type_error = || {
+            QueryError::new(
+                self.query,
+                position,
+                format!("'{}' is compared with '{}', which is not {}", field.name(), text, field.value_type()),
+            )
+        };
+        match field.value_type() {
+            Type::Level => text.parse::<LogLevel>().map(Value::Level).map_err(|_| type_error()),
+            Type::Time => text.parse::<DateTime>().map(Value::Time).map_err(|_| type_error()),
+            Type::Text => Ok(Value::Text(text)),
+        }
+    }
+}
+
+impl FromStr for Query {
+    type Err = QueryError;
+
+    fn from_str(query: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(query)?;
+        Parser { query, tokens, pos: 0 }.query()
+    }
+}
+
+// ---------------- evaluation ----------------
+
+// Running value of one aggregate column within a group.
+#[derive(Debug, Clone)]
+enum Accumulator {
+    Count(u64),
+    Extreme(Option<Value>),
+}
+
+impl Query {
+    fn is_grouped(&self) -> bool {
+        !self.group_by.is_empty() || self.select.iter().any(|item| matches!(item.column, Column::Aggregate(..)))
+    }
+
+    fn new_accumulators(&self) -> Vec<Accumulator> {
+        self.select
+            .iter()
+            .map(|item| match item.column {
+                Column::Aggregate(Aggregate::Count, _) => Accumulator::Count(0),
+                _ => Accumulator::Extreme(None),
+            })
+            .collect()
+    }
+
+    // Evaluates the query over `entries`.
+    pub fn run(&self, entries: &[LogEntry]) -> QueryResult {
+        let matching = entries.iter().filter(|entry| self.filter.as_ref().is_none_or(|f| f.eval(entry)));
+
+        let mut rows: Vec<Vec<Value>> = if self.is_grouped() {
+            // Groups come out in key order unless ORDER BY says otherwise
+            let mut groups: BTreeMap<Vec<Value>, Vec<Accumulator>> = BTreeMap::new();
+            for entry in matching {
+                let key: Vec<Value> = self.group_by.iter().map(|field| field.value(entry)).collect();
+                let accumulators = groups.entry(key).or_insert_with(|| self.new_accumulators());
+
+                for (item, acc) in self.select.iter().zip(accumulators.iter_mut()) {
+                    let Column::Aggregate(aggregate, field) = &item.column else {
+                        continue;
+                    };
+                    let value = field.as_ref().map(|f| f.value(entry));
+                    match (aggregate, acc) {
+                        (Aggregate::Count, Accumulator::Count(n)) if value.as_ref().is_none_or(|v| *v != Value::Null) => *n += 1,
+                        (aggregate, Accumulator::Extreme(current)) => {
+                            let Some(value) = value.filter(|v| *v != Value::Null) else {
+                                continue;
+                            };
+                            let replace = current.as_ref().is_none_or(|c| match aggregate {
+                                Aggregate::Min => value < *c,
+                                _ => value > *c,
+                            });
+                            if replace {
+                                *current = Some(value);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+
+            // An aggregate over no entries at all still gives one row (count = 0)
+            if groups.is_empty() && self.group_by.is_empty() {
+                groups.insert(Vec::new(), self.new_accumulators());
+            }
+
+            groups
+                .into_iter()
+                .map(|(key, accumulators)| {
+                    self.select
+                        .iter()
+                        .zip(accumulators)
+                        .map(|(item, acc)| match (&item.column, acc) {
+                            (Column::Field(field), _) => {
+                                let index = self.group_by.iter().position(|g| g == field).expect("checked when parsing");
+                                key[index].clone()
+                            }
+                            (_, Accumulator::Count(n)) => Value::Number(n),
+                            (_, Accumulator::Extreme(value)) => value.unwrap_or(Value::Null),
+                        })
+                        .collect()
+                })
+                .collect()
+        } else {
+            matching
+                .map(|entry| {
+                    self.select
+                        .iter()
+                        .map(|item| match &item.column {
+                            Column::Field(field) => field.value(entry),
+                            Column::Aggregate(..) => unreachable!("aggregates make the query grouped"),
+                        })
+                        .collect()
+                })
+                .collect()
+        };
+
+        // Stable sort, so ties keep key (or entry) order
+        rows.sort_by(|a, b| {
+            self.order_by
+                .iter()
+                .map(|&(index, descending)| {
+                    let ordering = a[index].cmp(&b[index]);
+                    if descending { ordering.reverse() } else { ordering }
+                })
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        if let Some(limit) = self.limit {
+            rows.truncate(limit);
+        }
+
+        QueryResult {
+            columns: self.select.iter().map(|item| item.name.clone()).collect(),
+            rows,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+impl QueryResult {
+    // Left-aligned text columns with a header line.
+    pub fn render_table(&self) -> String {
+        let cells: Vec<Vec<String>> = self
+            .rows
+            .iter()
+            .map(|row| row.iter().map(|value| value.to_string().replace('\n', "\\n")).collect())
+            .collect();
+
+        let widths: Vec<usize> = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                cells
+                    .iter()
+                    .map(|row| row[i].chars().count())
+                    .chain(std::iter::once(name.chars().count()))
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        let mut out = String::new();
+        let line = |out: &mut String, values: &mut dyn Iterator<Item = &String>| {
+            let text: Vec<String> = values
+                .zip(&widths)
+                .map(|(value, width)| format!("{:<width$}", value, width = width))
+                .collect();
+            let _ = writeln!(out, "{}", text.join("  ").trim_end());
+        };
+
+        line(&mut out, &mut self.columns.iter());
+        let rules: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
+        line(&mut out, &mut rules.iter());
+        for row in &cells {
+            line(&mut out, &mut row.iter());
+        }
+        let _ = writeln!(out, "({} rows)", self.rows.len());
+        out
+    }
+
+    // An array with one object per row, keyed by column name.
+    pub fn to_json(&self) -> serde_json::Value {
+        self.rows
+            .iter()
+            .map(|row| {
+                self.columns
+                    .iter()
+                    .zip(row)
+                    .map(|(name, value)| (name.clone(), value.to_json()))
+                    .collect::<serde_json::Map<_, _>>()
+            })
+            .map(serde_json::Value::Object)
+            .collect()
+    }
+}
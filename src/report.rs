@@ -1,9 +1,34 @@
+use crate::anomaly::Anomaly;
+use crate::date_time::{format_duration, DateTime};
+use crate::diff::{CountChange, StatisticsDiff, TemplateChange};
+use crate::format_detector::DetectedFormat;
+use crate::gaps::Gap;
+use crate::log_level::LogLevel;
+use crate::snapshot::sorted_map;
 use crate::statistics_aggregator::Statistics;
-use std::collections::HashMap;
-use serde::Serialize;
+use crate::templates::TemplateStats;
+use crate::timeseries::{Bucket, BucketWidth, TimeSeries};
+use crate::walk::SkippedFile;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use serde::{Serialize, Serializer};
 
+// Message templates listed in the reports, most frequent first.
+pub const TOP_TEMPLATES: usize = 10;
 
-pub fn print_report(stats: &Statistics, parse_error_count: usize) {
+// Run details reported next to the statistics.
+#[derive(Default)]
+pub struct ReportContext<'a> {
+    pub parse_error_count: usize,
+    pub filtered_count: usize, // entries rejected by the entry filter
+    pub detected_formats: &'a [DetectedFormat],
+    pub skipped_files: &'a [SkippedFile],
+    pub anomalies: Option<&'a [Anomaly]>, // None when detection was not run
+    pub gaps: Option<&'a [Gap]>,           // None when detection was not run
+}
+
+pub fn print_report(stats: &Statistics, ctx: &ReportContext) {
     println!("================== LOG ANALYSIS REPORT ==================");
 
     if let (Some(first), Some(last)) = (&stats.first_entry, &stats.last_entry) {
@@ -41,65 +66,336 @@ pub fn print_report(stats: &Statistics, parse_error_count: usize) {
         println!("{:<10} {} ({:.1}%)", comp, count, percent);
     }
 
-    println!("Parse Errors: {} lines skipped", parse_error_count);
+    if let Some(timeline) = &stats.timeline {
+        println!("TIMELINE ({} buckets, UTC)", timeline.width());
+        println!("--------");
+        print!("{}", render_timeline(timeline));
+    }
+
+    if let Some(anomalies) = ctx.anomalies {
+        println!("ANOMALIES");
+        println!("---------");
+
+        if anomalies.is_empty() {
+            println!("None found");
+        }
+        for anomaly in anomalies {
+            println!(
+                "{} to {}: {}, {} observed vs ~{:.0} expected (score {:.1})",
+                anomaly.start, anomaly.end, anomaly.kind, anomaly.observed, anomaly.expected, anomaly.score
+            );
+            let components: Vec<String> = anomaly.top_components.iter().map(|(c, n)| format!("{} {}", c, n)).collect();
+            if !components.is_empty() {
+                println!("    components: {}", components.join(", "));
+            }
+            for sample in &anomaly.samples {
+                println!("    {} [{}] {}: {}", sample.timestamp, sample.level, sample.component, sample.message);
+            }
+        }
+    }
+
+    if let Some(gaps) = ctx.gaps {
+        println!("COMPONENT GAPS");
+        println!("--------------");
+
+        if gaps.is_empty() {
+            println!("None found");
+        }
+        for gap in gaps {
+            let end = if gap.trailing { format!("{} (end of input)", gap.end) } else { gap.end.to_string() };
+            print!("{:<10} {} to {}: silent for {}", gap.component, gap.start, end, format_duration(gap.duration));
+            match gap.usual {
+                Some(usual) => println!(", usually every {}", format_duration(usual)),
+                None => println!(),
+            }
+        }
+    }
+
+    if let Some(templates) = &stats.templates {
+        println!("TOP MESSAGE TEMPLATES ({} distinct)", templates.len());
+        println!("---------------------");
+
+        for template in templates.top(TOP_TEMPLATES) {
+            let percent = (template.count as f64 / stats.total_entries as f64) * 100.0;
+            println!("{} ({:.1}%) [{}] {}", template.count, percent, levels_summary(template), template.template);
+            println!("    seen {} to {}, e.g. \"{}\"", template.first_seen, template.last_seen, template.example);
+        }
+    }
+
+    if !ctx.detected_formats.is_empty() {
+        println!("INPUT FORMATS");
+        println!("-------------");
+
+        for detected in ctx.detected_formats {
+            println!("{:<10} {} ({:.1}% confidence)", detected.format, detected.path.display(), detected.confidence * 100.0);
+        }
+    }
+
+    if !ctx.skipped_files.is_empty() {
+        println!("SKIPPED FILES");
+        println!("-------------");
+
+        for skipped in ctx.skipped_files {
+            println!("{} ({})", skipped.path.display(), skipped.reason);
+        }
+    }
+
+    if ctx.filtered_count > 0 {
+        println!("Filtered Out: {} entries", ctx.filtered_count);
+    }
+    println!("Parse Errors: {} lines skipped", ctx.parse_error_count);
+    println!("=========================================================");
+}
+
+// Differences between a baseline run and the current one, in the style of print_report.
+pub fn print_diff(diff: &StatisticsDiff) {
+    println!("================= LOG COMPARISON REPORT =================");
+    println!("Baseline -> Current");
+    println!("SUMMARY");
+    println!("-------");
+    println!("Total Entries: {} -> {} ({})", diff.total_before, diff.total_after, relative_change(diff.total_before, diff.total_after));
+    println!(
+        "Error Rate: {:.2}% -> {:.2}% ({:+.2} pp)",
+        diff.error_rate_before * 100.0,
+        diff.error_rate_after * 100.0,
+        (diff.error_rate_after - diff.error_rate_before) * 100.0
+    );
+
+    println!("BY LOG LEVEL");
+    println!("------------");
+    for change in &diff.by_level {
+        print_count_change(change);
+    }
+
+    println!("BY COMPONENT");
+    println!("------------");
+    for change in &diff.by_component {
+        print_count_change(change);
+    }
+
+    if !diff.share_changes.is_empty() {
+        println!("SHARE CHANGES");
+        println!("-------------");
+        for change in &diff.share_changes {
+            println!(
+                "{:<10} {:.1}% -> {:.1}% ({:+.1} pp, z {:+.1})",
+                change.component,
+                change.before * 100.0,
+                change.after * 100.0,
+                (change.after - change.before) * 100.0,
+                change.z_score
+            );
+        }
+    }
+
+    if let Some(templates) = &diff.new_templates {
+        println!("NEW TEMPLATES ({})", templates.len());
+        println!("-------------");
+        print_template_changes(templates);
+    }
+    if let Some(templates) = &diff.gone_templates {
+        println!("GONE TEMPLATES ({})", templates.len());
+        println!("--------------");
+        print_template_changes(templates);
+    }
     println!("=========================================================");
 }
 
+pub fn print_diff_json(diff: &StatisticsDiff) {
+    println!("{}", serde_json::to_string_pretty(diff).unwrap());
+}
+
+fn print_count_change(change: &CountChange) {
+    println!("{:<10} {} -> {} ({})", change.name, change.before, change.after, relative_change(change.before, change.after));
+}
+
+fn print_template_changes(templates: &[TemplateChange]) {
+    for template in templates.iter().take(TOP_TEMPLATES) {
+        println!("{} {}", template.count, template.template);
+        println!("    e.g. \"{}\"", template.example);
+    }
+    if templates.len() > TOP_TEMPLATES {
+        println!("... and {} more", templates.len() - TOP_TEMPLATES);
+    }
+}
+
+// "+30, +25.0%", or "+5, new" when there was nothing before.
+fn relative_change(before: usize, after: usize) -> String {
+    let delta = after as i64 - before as i64;
+    if before == 0 {
+        return if after == 0 { "+0".to_string() } else { format!("{:+}, new", delta) };
+    }
+    format!("{:+}, {:+.1}%", delta, delta as f64 / before as f64 * 100.0)
+}
+
+// "ERROR 3, WARN 1", most severe first.
+fn levels_summary(template: &TemplateStats) -> String {
+    let levels: Vec<String> = template.by_level.iter().rev().map(|(level, count)| format!("{} {}", level, count)).collect();
+    levels.join(", ")
+}
+
+// One line per bucket with a bar scaled to the busiest bucket:
+// 2024-01-15 10:05:00 |#########           | 18 (3 errors)
+// Runs of more than a few empty buckets are collapsed into a single line.
+pub fn render_timeline(timeline: &TimeSeries) -> String {
+    const BAR_WIDTH: usize = 40;
+    const MAX_EMPTY_RUN: usize = 3;
+
+    let max = timeline.peak().map_or(0, |b| b.total).max(1);
+    let count_width = max.to_string().len();
+
+    let mut out = String::new();
+    let mut empty_run: Vec<Bucket> = Vec::new();
+    let mut empty_count = 0;
+    for bucket in timeline.buckets() {
+        if bucket.total == 0 {
+            // Only the first few are needed to print a short run
+            if empty_run.len() <= MAX_EMPTY_RUN {
+                empty_run.push(bucket);
+            }
+            empty_count += 1;
+            continue;
+        }
+        // The last bucket always has entries, so every empty run ends here
+        if empty_count > MAX_EMPTY_RUN {
+            let _ = writeln!(out, "{} ... {} empty buckets", empty_run[0].start, empty_count);
+        } else {
+            for empty in &empty_run {
+                let _ = writeln!(out, "{} |{:<bar$}| {:>count$}", empty.start, "", 0, bar = BAR_WIDTH, count = count_width);
+            }
+        }
+        empty_run.clear();
+        empty_count = 0;
+
+        let filled = (bucket.total * BAR_WIDTH).div_ceil(max);
+        let _ = write!(
+            out,
+            "{} |{:<bar$}| {:>count$}",
+            bucket.start,
+            "#".repeat(filled),
+            bucket.total,
+            bar = BAR_WIDTH,
+            count = count_width
+        );
+        if bucket.errors() > 0 {
+            let _ = write!(out, " ({} errors)", bucket.errors());
+        }
+        out.push('\n');
+    }
+    out
+}
 
 // json report
 
 #[derive(Serialize)]
-struct PeriodJson {
-    start: String,
-    end: String,
+struct PeriodJson<'a> {
+    start: &'a DateTime,
+    end: &'a DateTime,
+}
+
+// A timeline bucket as listed in the report; empty buckets are listed too.
+#[derive(Serialize)]
+struct BucketJson<'a> {
+    start: DateTime,
+    total: usize,
+    by_level: Cow<'a, BTreeMap<LogLevel, usize>>,
+    by_component: Cow<'a, BTreeMap<String, usize>>,
+}
+
+#[derive(Serialize)]
+struct TimelineJson<'a> {
+    bucket_width: BucketWidth,
+    buckets: BucketsJson<'a>,
 }
 
+// Serializes the zero-filled buckets one at a time, without collecting them.
+struct BucketsJson<'a>(&'a TimeSeries);
+
+impl Serialize for BucketsJson<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.bucket_slots().map(|(start, bucket)| match bucket {
+            Some(bucket) => BucketJson {
+                start,
+                total: bucket.total,
+                by_level: Cow::Borrowed(&bucket.by_level),
+                by_component: Cow::Borrowed(&bucket.by_component),
+            },
+            None => BucketJson {
+                start,
+                total: 0,
+                by_level: Cow::Owned(BTreeMap::new()),
+                by_component: Cow::Owned(BTreeMap::new()),
+            },
+        }))
+    }
+}
+
+// Borrows everything from the statistics and the run details, so building it copies nothing.
 #[derive(Serialize)]
-struct ReportJson {
+struct ReportJson<'a> {
     total_entries: usize,
     error_rate: f64,
     peak_hour: Option<u8>,
-    most_active_component: Option<String>,
-    entries_by_level: HashMap<String, usize>,
-    entries_by_component: HashMap<String, usize>,
-    period: Option<PeriodJson>,
+    most_active_component: Option<&'a str>,
+    #[serde(serialize_with = "sorted_map")]
+    entries_by_level: &'a HashMap<LogLevel, usize>,
+    #[serde(serialize_with = "sorted_map")]
+    entries_by_component: &'a HashMap<String, usize>,
+    period: Option<PeriodJson<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timeline: Option<TimelineJson<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    templates: Option<Vec<&'a TemplateStats>>, // the TOP_TEMPLATES most frequent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    anomalies: Option<&'a [Anomaly]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gaps: Option<&'a [Gap]>,
     parse_errors: usize,
+    #[serde(skip_serializing_if = "is_zero")]
+    filtered_out: usize,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    detected_formats: &'a [DetectedFormat],
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    skipped_files: &'a [SkippedFile],
 }
 
-pub fn print_report_json(stats: &Statistics, parse_error_count: usize) {
-
-    // Convert LogLevel -> String so we don't touch enum
-    let entries_by_level_json: HashMap<String, usize> =
-        stats.entries_by_level
-            .iter()
-            .map(|(lvl, count)| (lvl.to_string(), *count))
-            .collect();
-
-    // Period
-    let period_json = if let (Some(first), Some(last)) =
-        (&stats.first_entry, &stats.last_entry)
-    {
-        Some(PeriodJson {
-            start: first.to_string(),
-            end: last.to_string(),
-        })
-    } else {
-        None
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+pub fn print_report_json(stats: &Statistics, ctx: &ReportContext) {
+    println!("{}", serde_json::to_string_pretty(&report_json(stats, ctx)).unwrap());
+}
+
+// One compact JSON object per line, for follow mode snapshots.
+pub fn print_report_json_line(stats: &Statistics, ctx: &ReportContext) {
+    println!("{}", serde_json::to_string(&report_json(stats, ctx)).unwrap());
+}
+
+fn report_json<'a>(stats: &'a Statistics, ctx: &ReportContext<'a>) -> ReportJson<'a> {
+    let period = match (&stats.first_entry, &stats.last_entry) {
+        (Some(start), Some(end)) => Some(PeriodJson { start, end }),
+        _ => None,
     };
 
-    // Build final ordered struct
-    let report = ReportJson {
+    ReportJson {
         total_entries: stats.total_entries,
         error_rate: stats.error_rate,
         peak_hour: stats.peak_hour,
-        most_active_component: stats.most_active_component.clone(),
-        entries_by_level: entries_by_level_json,
-        entries_by_component: stats.entries_by_component.clone(),
-        period: period_json,
-        parse_errors: parse_error_count,
-    };
-
-    // Print JSON
-    println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        most_active_component: stats.most_active_component.as_deref(),
+        entries_by_level: &stats.entries_by_level,
+        entries_by_component: &stats.entries_by_component,
+        period,
+        timeline: stats.timeline.as_ref().map(|timeline| TimelineJson {
+            bucket_width: timeline.width(),
+            buckets: BucketsJson(timeline),
+        }),
+        templates: stats.templates.as_ref().map(|templates| templates.top(TOP_TEMPLATES)),
+        anomalies: ctx.anomalies,
+        gaps: ctx.gaps,
+        parse_errors: ctx.parse_error_count,
+        filtered_out: ctx.filtered_count,
+        detected_formats: ctx.detected_formats,
+        skipped_files: ctx.skipped_files,
+    }
 }
-
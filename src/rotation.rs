@@ -0,0 +1,55 @@
+use std::cmp::Reverse;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+// Position of a file within its rotation set, oldest first.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Generation {
+    Dated(String),           // app.log.2024-01-15, app-2024-01-15.log, app.log.20240115
+    Numbered(Reverse<u64>),  // app.log.1 is newer than app.log.2
+    Current,                 // app.log
+}
+
+static DATE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(\d{4})-?(\d{2})-?(\d{2})").expect("valid date pattern"));
+
+const COMPRESSION_EXTENSIONS: [&str; 5] = ["gz", "gzip", "zst", "zstd", "bz2"];
+
+// Splits a file name into the name shared by its rotation set and its generation.
+fn rotation_key(path: &Path) -> (PathBuf, String, Generation) {
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    let mut name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    if let Some((stem, ext)) = name.rsplit_once('.')
+        && COMPRESSION_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str())
+    {
+        name = stem.to_string();
+    }
+
+    if let Some((base, suffix)) = name.rsplit_once('.')
+        && let Ok(n) = suffix.parse::<u64>()
+        && suffix.len() < 8
+    {
+        return (dir, base.to_string(), Generation::Numbered(Reverse(n)));
+    }
+
+    if let Some(caps) = DATE.captures(&name) {
+        let date = format!("{}-{}-{}", &caps[1], &caps[2], &caps[3]);
+        let whole = caps.get(0).expect("match has group 0");
+        let base = format!("{}{}", name[..whole.start()].trim_end_matches(['.', '-', '_']), &name[whole.end()..]);
+        return (dir, base, Generation::Dated(date));
+    }
+
+    (dir, name, Generation::Current)
+}
+
+// Sorts files so that each rotation set reads oldest to newest:
+// app.log.2024-01-14, app.log.2, app.log.1, app.log. Sets are ordered by directory and name.
+pub fn sort_by_rotation(files: &mut [PathBuf]) {
+    files.sort_by_cached_key(|path| (rotation_key(path), path.clone()));
+}
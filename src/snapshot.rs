@@ -0,0 +1,79 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize, Serializer};
+use thiserror::Error;
+
+use crate::statistics_aggregator::Statistics;
+
+// Version of the snapshot layout. Bump it when a change would make older
+// snapshots read back wrongly; new optional fields do not need a bump.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("failed to access snapshot {}: {source}", .path.display())]
+    IoError { path: PathBuf, source: io::Error },
+    #[error("invalid snapshot {}: {source}", .path.display())]
+    InvalidSnapshot { path: PathBuf, source: serde_json::Error },
+    #[error("snapshot {} has schema version {found}, this build reads version {SCHEMA_VERSION}", .path.display())]
+    UnsupportedVersion { path: PathBuf, found: u32 },
+}
+
+// What is written to disk: the statistics plus the schema version they were written with.
+#[derive(Serialize, Deserialize)]
+struct Snapshot<T> {
+    schema_version: u32,
+    statistics: T,
+}
+
+// Writes the statistics to `path` as pretty-printed JSON, with map keys sorted so
+// snapshots of the same input are identical and diff well.
+pub fn save_snapshot(stats: &Statistics, path: &Path) -> Result<(), SnapshotError> {
+    let snapshot = Snapshot {
+        schema_version: SCHEMA_VERSION,
+        statistics: stats,
+    };
+    let json = serde_json::to_string_pretty(&snapshot).map_err(|source| SnapshotError::InvalidSnapshot {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    fs::write(path, json + "\n").map_err(|source| SnapshotError::IoError {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+// Reads statistics saved by save_snapshot.
+pub fn load_snapshot(path: &Path) -> Result<Statistics, SnapshotError> {
+    let json = fs::read_to_string(path).map_err(|source| SnapshotError::IoError {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let invalid = |source| SnapshotError::InvalidSnapshot {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    // Check the version before the layout, so a newer snapshot gets a clear error
+    let snapshot: Snapshot<serde_json::Value> = serde_json::from_str(&json).map_err(invalid)?;
+    if snapshot.schema_version != SCHEMA_VERSION {
+        return Err(SnapshotError::UnsupportedVersion {
+            path: path.to_path_buf(),
+            found: snapshot.schema_version,
+        });
+    }
+    Statistics::deserialize(snapshot.statistics).map_err(invalid)
+}
+
+// Serializes a HashMap with its keys in order, for `#[serde(serialize_with)]`.
+pub(crate) fn sorted_map<K, V, S>(map: &HashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    K: Ord + Serialize,
+    V: Serialize,
+    S: Serializer,
+{
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
@@ -1,13 +1,23 @@
 use crate::date_time::DateTime;
 use crate::log_level::LogLevel;
 use crate::log_entry::LogEntry;
+use crate::gaps::ComponentActivity;
+use crate::templates::TemplateMiner;
+use crate::timeseries::{BucketWidth, TimeSeries};
+use crate::snapshot::sorted_map;
 use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
+// Serializable for snapshots (see snapshot.rs); the optional aggregates are left
+// out when they are not enabled.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Statistics {
     pub total_entries: usize,
+    #[serde(serialize_with = "sorted_map")]
     pub entries_by_level: HashMap<LogLevel, usize>,
+    #[serde(serialize_with = "sorted_map")]
     pub entries_by_component: HashMap<String, usize>,
+    #[serde(serialize_with = "sorted_map")]
     pub entries_by_hour: HashMap<u8, usize>,
     pub error_count: usize, // ERROR + FATAL
     pub error_rate: f64,    // error_count / total_entries
@@ -15,103 +25,208 @@ pub struct Statistics {
     pub peak_hour: Option<u8>,
     pub first_entry: Option<DateTime>,
     pub last_entry: Option<DateTime>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeline: Option<TimeSeries>, // counts over absolute time, when enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub templates: Option<TemplateMiner>, // entries grouped by message template, when enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub activity: Option<ComponentActivity>, // when each component logged, for gap detection, when enabled
+}
+
+impl Default for Statistics {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Statistics {
+    pub fn new() -> Self {
+        Statistics {
+            total_entries: 0,
+            entries_by_level: HashMap::new(),
+            entries_by_component: HashMap::new(),
+            entries_by_hour: HashMap::new(),
+            error_count: 0,
+            error_rate: 0.0,
+            most_active_component: None,
+            peak_hour: None,
+            first_entry: None,
+            last_entry: None,
+            timeline: None,
+            templates: None,
+            activity: None,
+        }
+    }
 
-    pub fn from_entries(entries: &[LogEntry]) -> Self {
-        let total_entries = entries.len();
-        let mut entries_by_level: HashMap<LogLevel, usize> = HashMap::new();
-        let mut entries_by_component: HashMap<String, usize> = HashMap::new();
-        let mut entries_by_hour: HashMap<u8, usize> = HashMap::new();
-        let mut error_count: usize = 0;
-        let mut first_entry: Option<DateTime> = None;
-        let mut last_entry: Option<DateTime> = None;
+    // Also count entries per time bucket of `width` (see TimeSeries).
+    pub fn with_timeline(mut self, width: BucketWidth) -> Self {
+        self.timeline = Some(TimeSeries::new(width));
+        self
+    }
+
+    // Also group entries by message template (see templates::message_template).
+    pub fn with_templates(mut self) -> Self {
+        self.templates = Some(TemplateMiner::new());
+        self
+    }
+
+    // Also record when each component logged (see gaps::find_gaps).
+    pub fn with_activity(mut self) -> Self {
+        self.activity = Some(ComponentActivity::new());
+        self
+    }
 
+    // Empty statistics with the same options, e.g. for one chunk that is merged in later.
+    pub fn empty_like(&self) -> Self {
+        Statistics {
+            timeline: self.timeline.as_ref().map(|t| TimeSeries::new(t.width())),
+            templates: self.templates.as_ref().map(|_| TemplateMiner::new()),
+            activity: self.activity.as_ref().map(|_| ComponentActivity::new()),
+            ..Statistics::new()
+        }
+    }
+
+    pub fn from_entries(entries: &[LogEntry]) -> Self {
+        let mut stats = Statistics::new();
         for entry in entries {
-            // Entries by Level Count
-            *entries_by_level.entry(entry.level).or_insert(0) += 1;
+            stats.add(entry);
+        }
+        stats
+    }
+
+    // Folds one more entry into the statistics. Ties for the most active
+    // component and the peak hour go to the smallest name / hour.
+    pub fn add(&mut self, entry: &LogEntry) {
+        self.total_entries += 1;
+
+        // Entries by Level Count
+        *self.entries_by_level.entry(entry.level).or_insert(0) += 1;
 
-            if let Some(v) = entries_by_component.get_mut(&entry.component) {
+        let count = match self.entries_by_component.get_mut(&entry.component) {
+            Some(v) => {
                 *v += 1;
+                *v
             }
-            else {
-                entries_by_component.insert(entry.component.clone(), 1);
+            None => {
+                self.entries_by_component.insert(entry.component.clone(), 1);
+                1
             }
+        };
 
-            // Entries by Hour Count
-            let hour = entry.timestamp.hour;
-            *entries_by_hour.entry(hour).or_insert(0) += 1;
-
-            // Error Count
-            if entry.level == LogLevel::Error || entry.level == LogLevel::Fatal {
-                error_count += 1;
+        // most active component
+        let leads = match &self.most_active_component {
+            None => true,
+            Some(current) => {
+                let max_count = self.entries_by_component[current];
+                count > max_count || (count == max_count && entry.component < *current)
             }
+        };
+        if leads {
+            self.most_active_component = Some(entry.component.clone());
+        }
 
-            first_entry = match first_entry {
-                None => Some(entry.timestamp.clone()),
-                Some(curr) => {
-                    if entry.timestamp < curr {
-                        Some(entry.timestamp.clone())
-                    }
-                    else{
-                        Some(curr)
-                    }
-                }
-            };
-
-            last_entry = match last_entry {
-                None => Some(entry.timestamp.clone()),
-                Some(curr) => {
-                    if entry.timestamp > curr {
-                        Some(entry.timestamp.clone())
-                    } else {
-                        Some(curr)
-                    }
-                }
-            };
+        // Entries by Hour Count (UTC, so hosts in different zones line up)
+        let hour = entry.timestamp.to_utc().hour;
+        let count = {
+            let v = self.entries_by_hour.entry(hour).or_insert(0);
+            *v += 1;
+            *v
+        };
 
+        // peak hour
+        let leads = match self.peak_hour {
+            None => true,
+            Some(current) => {
+                let max_count = self.entries_by_hour[&current];
+                count > max_count || (count == max_count && hour < current)
+            }
+        };
+        if leads {
+            self.peak_hour = Some(hour);
         }
 
-        // most active component
-        let mut most_active_component: Option<String> = None;
-        let mut max_count: usize = 0;
+        // Error Count
+        if entry.level == LogLevel::Error || entry.level == LogLevel::Fatal {
+            self.error_count += 1;
+        }
+        self.error_rate = self.error_count as f64 / self.total_entries as f64;
 
-        for (component, count) in &entries_by_component {
-            if most_active_component.is_none() || *count > max_count {
-                max_count = *count;
-                most_active_component = Some(component.clone());
-            }
+        if self.first_entry.as_ref().is_none_or(|first| entry.timestamp < *first) {
+            self.first_entry = Some(entry.timestamp.clone());
+        }
+        if self.last_entry.as_ref().is_none_or(|last| entry.timestamp > *last) {
+            self.last_entry = Some(entry.timestamp.clone());
         }
-    
-        // peak hour
-        let mut peak_hour: Option<u8> = None;
-        let mut max_count_peak_hour: usize = 0;
 
-        for (hour, count) in &entries_by_hour {
-            if peak_hour.is_none() || *count > max_count_peak_hour {
-                max_count_peak_hour = *count;
-                peak_hour = Some(*hour);
-            }
+        if let Some(timeline) = &mut self.timeline {
+            timeline.add(entry);
+        }
+        if let Some(templates) = &mut self.templates {
+            templates.add(entry);
+        }
+        if let Some(activity) = &mut self.activity {
+            activity.add(entry);
         }
-        // error rate
-        let mut error_rate = 0.0;
+    }
+
+    // Combines statistics gathered separately (other files, workers or days).
+    // The result is the same as a single pass over both sets of entries.
+    pub fn merge(&mut self, other: &Statistics) {
+        self.total_entries += other.total_entries;
+        self.error_count += other.error_count;
 
-        if total_entries > 0 {
-            error_rate = error_count as f64 / total_entries as f64;
+        for (level, count) in &other.entries_by_level {
+            *self.entries_by_level.entry(*level).or_insert(0) += count;
+        }
+        for (component, count) in &other.entries_by_component {
+            *self.entries_by_component.entry(component.clone()).or_insert(0) += count;
+        }
+        for (hour, count) in &other.entries_by_hour {
+            *self.entries_by_hour.entry(*hour).or_insert(0) += count;
         }
 
-        Statistics {
-            total_entries,
-            entries_by_level,
-            entries_by_component,
-            entries_by_hour,
-            error_count,
-            error_rate,
-            most_active_component,
-            peak_hour,
-            first_entry,
-            last_entry,
+        if let Some(first) = &other.first_entry
+            && self.first_entry.as_ref().is_none_or(|current| first < current)
+        {
+            self.first_entry = Some(first.clone());
+        }
+        if let Some(last) = &other.last_entry
+            && self.last_entry.as_ref().is_none_or(|current| last > current)
+        {
+            self.last_entry = Some(last.clone());
         }
+
+        match (&mut self.timeline, &other.timeline) {
+            (Some(ours), Some(theirs)) => ours.merge(theirs),
+            (None, Some(theirs)) => self.timeline = Some(theirs.clone()),
+            _ => {}
+        }
+        match (&mut self.templates, &other.templates) {
+            (Some(ours), Some(theirs)) => ours.merge(theirs),
+            (None, Some(theirs)) => self.templates = Some(theirs.clone()),
+            _ => {}
+        }
+        match (&mut self.activity, &other.activity) {
+            (Some(ours), Some(theirs)) => ours.merge(theirs),
+            (None, Some(theirs)) => self.activity = Some(theirs.clone()),
+            _ => {}
+        }
+
+        // Derived fields, with the same tie-breaking as add
+        self.error_rate = if self.total_entries > 0 {
+            self.error_count as f64 / self.total_entries as f64
+        } else {
+            0.0
+        };
+        self.most_active_component = self
+            .entries_by_component
+            .iter()
+            .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0)))
+            .map(|(component, _)| component.clone());
+        self.peak_hour = self
+            .entries_by_hour
+            .iter()
+            .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0)))
+            .map(|(hour, _)| *hour);
     }
 }
@@ -0,0 +1,226 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::date_time::DateTime;
+use crate::log_entry::LogEntry;
+use crate::log_level::LogLevel;
+use crate::snapshot::sorted_map;
+
+// Placeholders for the variable parts of a message.
+pub const NUM: &str = "<NUM>";
+pub const PATH: &str = "<PATH>";
+pub const IP: &str = "<IP>";
+pub const HEX: &str = "<HEX>";
+pub const UUID: &str = "<UUID>";
+
+// Punctuation that is kept around a token but not part of its value:
+// "(id=42)," keeps its parentheses and comma around the masked value.
+const OPENING: &[char] = &['(', '[', '{', '"', '\'', '<'];
+const CLOSING: &[char] = &[')', ']', '}', '"', '\'', '>', ',', ';', ':', '.', '!', '?'];
+
+// The message with its variable tokens replaced by placeholders, in the spirit
+// of Drain's preprocessing: "Failed to mount filesystem /dev/sda1" and
+// "Failed to mount filesystem /dev/sdb2" both become "Failed to mount filesystem <PATH>".
+//
+// Tokens are split on whitespace, and a `key=value` token keeps its key. Words
+// are never merged into a wildcard, so the template of a message does not depend
+// on which other messages were seen and templates from separate runs combine exactly.
+pub fn message_template(message: &str) -> String {
+    let mut template = String::with_capacity(message.len());
+    for token in message.split_whitespace() {
+        if !template.is_empty() {
+            template.push(' ');
+        }
+        push_masked(&mut template, token);
+    }
+    template
+}
+
+fn push_masked(out: &mut String, token: &str) {
+    let start = token.len() - token.trim_start_matches(OPENING).len();
+    let core = token[start..].trim_end_matches(CLOSING);
+    let (prefix, suffix) = (&token[..start], &token[start + core.len()..]);
+
+    out.push_str(prefix);
+    match classify(core) {
+        Some(placeholder) => out.push_str(placeholder),
+        // key=value and key:value keep their key
+        None => match key_value(core) {
+            Some((key, placeholder)) => {
+                out.push_str(key);
+                out.push_str(placeholder);
+            }
+            None => out.push_str(core),
+        },
+    }
+    out.push_str(suffix);
+}
+
+// The key (with its separator) and the placeholder for the value of "id=42" or "port:8080".
+fn key_value(token: &str) -> Option<(&str, &'static str)> {
+    let split = token.find(['=', ':'])?;
+    let key = &token[..split];
+    if key.is_empty() || !key.chars().all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.')) {
+        return None;
+    }
+    Some((&token[..=split], classify(&token[split + 1..])?))
+}
+
+// Which kind of variable a token is, if any.
+fn classify(token: &str) -> Option<&'static str> {
+    if token.is_empty() {
+        None
+    } else if is_uuid(token) {
+        Some(UUID)
+    } else if is_ip(token) {
+        Some(IP)
+    } else if is_number(token) {
+        Some(NUM)
+    } else if is_hex(token) {
+        Some(HEX)
+    } else if is_path(token) {
+        Some(PATH)
+    } else {
+        None
+    }
+}
+
+// 8-4-4-4-12 hex digits
+fn is_uuid(token: &str) -> bool {
+    let groups: Vec<&str> = token.split('-').collect();
+    groups.len() == 5
+        && groups.iter().zip([8, 4, 4, 4, 12]).all(|(group, len)| group.len() == len && group.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+// IPv4 with an optional port, or IPv6
+fn is_ip(token: &str) -> bool {
+    let host = match token.rsplit_once(':') {
+        Some((host, port)) if host.contains('.') && !port.is_empty() && port.chars().all(|c| c.is_ascii_digit()) => host,
+        _ => token,
+    };
+    host.parse::<Ipv4Addr>().is_ok() || (token.matches(':').count() >= 2 && token.parse::<Ipv6Addr>().is_ok())
+}
+
+// 42, -7, 3.14, 1,024, 12:30:05, and numbers with a unit such as 250ms, 1.5s, 10MB or 99%
+fn is_number(token: &str) -> bool {
+    let digits = token.strip_prefix(['-', '+']).unwrap_or(token);
+    let unit_start = digits.find(|c: char| !(c.is_ascii_digit() || matches!(c, '.' | ',' | ':'))).unwrap_or(digits.len());
+    let (number, unit) = digits.split_at(unit_start);
+    number.starts_with(|c: char| c.is_ascii_digit())
+        && (unit.is_empty() || unit == "%" || (unit.len() <= 3 && unit.chars().all(|c| c.is_ascii_alphabetic())))
+}
+
+// 0x1f, or ids like 7f3a9c01 / a3f5e9b2c4d1: at least 8 hex digits with a digit among them
+fn is_hex(token: &str) -> bool {
+    if let Some(digits) = token.strip_prefix("0x").or_else(|| token.strip_prefix("0X")) {
+        return !digits.is_empty() && digits.chars().all(|c| c.is_ascii_hexdigit());
+    }
+    token.len() >= 8 && token.chars().all(|c| c.is_ascii_hexdigit()) && token.chars().any(|c| c.is_ascii_digit())
+}
+
+// /dev/sda1, ./config.toml, ~/.cache, C:\logs
+fn is_path(token: &str) -> bool {
+    (token.len() > 1 && token.starts_with('/'))
+        || token.starts_with("./")
+        || token.starts_with("../")
+        || token.starts_with("~/")
+        || (token.len() > 3 && token.as_bytes()[0].is_ascii_alphabetic() && token[1..].starts_with(":\\"))
+}
+
+// Everything known about the messages that share one template.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TemplateStats {
+    pub template: String,
+    pub count: usize,
+    pub by_level: BTreeMap<LogLevel, usize>,
+    pub first_seen: DateTime,
+    pub last_seen: DateTime,
+    pub example: String, // the message of the first entry seen
+}
+
+impl TemplateStats {
+    fn absorb(&mut self, other: &TemplateStats) {
+        self.count += other.count;
+        for (level, count) in &other.by_level {
+            *self.by_level.entry(*level).or_insert(0) += count;
+        }
+        // The earliest entry gives the example; the smaller message on a tie
+        if (&other.first_seen, &other.example) < (&self.first_seen, &self.example) {
+            self.first_seen = other.first_seen.clone();
+            self.example = other.example.clone();
+        }
+        if other.last_seen > self.last_seen {
+            self.last_seen = other.last_seen.clone();
+        }
+    }
+}
+
+// Groups entries by the template of their message.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TemplateMiner {
+    #[serde(serialize_with = "sorted_map")]
+    templates: HashMap<String, TemplateStats>,
+}
+
+impl TemplateMiner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, entry: &LogEntry) {
+        let template = message_template(&entry.message);
+        let seen = TemplateStats {
+            template: template.clone(),
+            count: 1,
+            by_level: BTreeMap::from([(entry.level, 1)]),
+            first_seen: entry.timestamp.clone(),
+            last_seen: entry.timestamp.clone(),
+            example: entry.message.clone(),
+        };
+        match self.templates.get_mut(&template) {
+            Some(stats) => stats.absorb(&seen),
+            None => {
+                self.templates.insert(template, seen);
+            }
+        }
+    }
+
+    pub fn merge(&mut self, other: &TemplateMiner) {
+        for (template, theirs) in &other.templates {
+            match self.templates.get_mut(template) {
+                Some(ours) => ours.absorb(theirs),
+                None => {
+                    self.templates.insert(template.clone(), theirs.clone());
+                }
+            }
+        }
+    }
+
+    // Number of distinct templates.
+    pub fn len(&self) -> usize {
+        self.templates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.templates.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &TemplateStats> {
+        self.templates.values()
+    }
+
+    pub fn get(&self, template: &str) -> Option<&TemplateStats> {
+        self.templates.get(template)
+    }
+
+    // The `n` most frequent templates; alphabetical on a tie.
+    pub fn top(&self, n: usize) -> Vec<&TemplateStats> {
+        let mut top: Vec<&TemplateStats> = self.templates.values().collect();
+        top.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.template.cmp(&b.template)));
+        top.truncate(n);
+        top
+    }
+}
@@ -0,0 +1,296 @@
+use std::cmp::Reverse;
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::date_time::{parse_duration, DateTime};
+use crate::log_entry::LogEntry;
+use crate::log_level::LogLevel;
+
+// Length of one time-series bucket. Buckets are aligned to the Unix epoch (UTC),
+// so a day bucket runs from 00:00 to 24:00 UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BucketWidth {
+    seconds: i64,
+}
+
+impl BucketWidth {
+    pub const SECOND: BucketWidth = BucketWidth { seconds: 1 };
+    pub const MINUTE: BucketWidth = BucketWidth { seconds: 60 };
+    pub const FIVE_MINUTES: BucketWidth = BucketWidth { seconds: 300 };
+    pub const HOUR: BucketWidth = BucketWidth { seconds: 3600 };
+    pub const DAY: BucketWidth = BucketWidth { seconds: 86_400 };
+
+    pub fn from_seconds(seconds: u32) -> Option<BucketWidth> {
+        (seconds > 0).then_some(BucketWidth { seconds: seconds as i64 })
+    }
+
+    pub fn seconds(&self) -> i64 {
+        self.seconds
+    }
+
+    fn bucket_start(&self, unix_seconds: i64) -> i64 {
+        unix_seconds.div_euclid(self.seconds) * self.seconds
+    }
+}
+
+// "second", "minute", "hour", "day", or a count with a unit: "30s", "5m", "2h", "1d".
+impl FromStr for BucketWidth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_ascii_lowercase();
+        match s.as_str() {
+            "second" => return Ok(BucketWidth::SECOND),
+            "minute" => return Ok(BucketWidth::MINUTE),
+            "hour" => return Ok(BucketWidth::HOUR),
+            "day" => return Ok(BucketWidth::DAY),
+            _ => {}
+        }
+
+        let invalid = || format!("invalid bucket width '{}': expected e.g. 30s, 5m, 1h or 1d", s);
+        let duration = parse_duration(&s).map_err(|_| invalid())?;
+        u32::try_from(duration.as_secs())
+            .ok()
+            .and_then(BucketWidth::from_seconds)
+            .ok_or_else(invalid)
+    }
+}
+
+impl fmt::Display for BucketWidth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.seconds {
+            s if s % 86_400 == 0 => write!(f, "{}d", s / 86_400),
+            s if s % 3600 == 0 => write!(f, "{}h", s / 3600),
+            s if s % 60 == 0 => write!(f, "{}m", s / 60),
+            s => write!(f, "{}s", s),
+        }
+    }
+}
+
+// Serialized as its Display form: "5m".
+impl Serialize for BucketWidth {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for BucketWidth {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+    }
+}
+
+// Messages kept per bucket as examples of what happened in it.
+pub const SAMPLES_PER_BUCKET: usize = 3;
+
+// One example entry of a bucket.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sample {
+    pub timestamp: DateTime,
+    pub level: LogLevel,
+    pub component: String,
+    pub message: String,
+}
+
+impl Sample {
+    // Most severe first, then earliest, so the kept samples do not depend on input order.
+    fn rank(&self) -> (Reverse<LogLevel>, &DateTime, &str, &str) {
+        (Reverse(self.level), &self.timestamp, &self.component, &self.message)
+    }
+}
+
+// Entries that fall into one bucket.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bucket {
+    pub start: DateTime, // UTC
+    pub total: usize,
+    pub by_level: BTreeMap<LogLevel, usize>,
+    pub by_component: BTreeMap<String, usize>,
+    pub errors_by_component: BTreeMap<String, usize>, // ERROR + FATAL only
+    pub samples: Vec<Sample>, // at most SAMPLES_PER_BUCKET, most severe first
+}
+
+impl Bucket {
+    fn empty(start: DateTime) -> Self {
+        Bucket {
+            start,
+            total: 0,
+            by_level: BTreeMap::new(),
+            by_component: BTreeMap::new(),
+            errors_by_component: BTreeMap::new(),
+            samples: Vec::new(),
+        }
+    }
+
+    fn add_samples<'a>(&mut self, samples: impl IntoIterator<Item = &'a Sample>) {
+        for sample in samples {
+            let at = self.samples.partition_point(|kept| kept.rank() <= sample.rank());
+            if at < SAMPLES_PER_BUCKET {
+                self.samples.insert(at, sample.clone());
+                self.samples.truncate(SAMPLES_PER_BUCKET);
+            }
+        }
+    }
+
+    pub fn errors(&self) -> usize {
+        self.by_level.get(&LogLevel::Error).unwrap_or(&0) + self.by_level.get(&LogLevel::Fatal).unwrap_or(&0)
+    }
+}
+
+// Entry counts over absolute time, one bucket per `width`. Only buckets that saw
+// entries are stored; buckets() fills the gaps between them with zeros.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimeSeries {
+    width: BucketWidth,
+    buckets: BTreeMap<i64, Bucket>, // keyed by bucket start in Unix seconds
+}
+
+impl TimeSeries {
+    pub fn new(width: BucketWidth) -> Self {
+        TimeSeries { width, buckets: BTreeMap::new() }
+    }
+
+    pub fn width(&self) -> BucketWidth {
+        self.width
+    }
+
+    pub fn add(&mut self, entry: &LogEntry) {
+        let start = self.width.bucket_start(entry.timestamp.unix_seconds());
+        let bucket = match self.buckets.entry(start) {
+            Entry::Occupied(bucket) => bucket.into_mut(),
+            Entry::Vacant(slot) => match bucket_time(start) {
+                Some(time) => slot.insert(Bucket::empty(time)),
+                // A hand-built timestamp outside 1970..=9999 has no bucket
+                None => return,
+            },
+        };
+        bucket.total += 1;
+        *bucket.by_level.entry(entry.level).or_insert(0) += 1;
+        *bucket.by_component.entry(entry.component.clone()).or_insert(0) += 1;
+        if entry.level >= LogLevel::Error {
+            *bucket.errors_by_component.entry(entry.component.clone()).or_insert(0) += 1;
+        }
+        // Only clone the message when it makes it into the samples
+        let rank = (Reverse(entry.level), &entry.timestamp, entry.component.as_str(), entry.message.as_str());
+        if bucket.samples.len() < SAMPLES_PER_BUCKET || bucket.samples.last().is_some_and(|last| rank < last.rank()) {
+            bucket.add_samples([&Sample {
+                timestamp: entry.timestamp.clone(),
+                level: entry.level,
+                component: entry.component.clone(),
+                message: entry.message.clone(),
+            }]);
+        }
+    }
+
+    // Adds the counts of another series. If the widths differ, both are first
+    // re-bucketed to the narrowest width that is a multiple of both (the coarser
+    // one when it is a multiple of the other), so that the buckets line up.
+    pub fn merge(&mut self, other: &TimeSeries) {
+        if self.width == other.width {
+            self.merge_buckets(other);
+            return;
+        }
+        let width = BucketWidth { seconds: lcm(self.width.seconds, other.width.seconds) };
+        if self.width != width {
+            *self = self.rebucketed(width);
+        }
+        if other.width != width {
+            self.merge_buckets(&other.rebucketed(width));
+        } else {
+            self.merge_buckets(other);
+        }
+    }
+
+    // The same counts in wider buckets; `width` must be a multiple of the current one.
+    fn rebucketed(&self, width: BucketWidth) -> TimeSeries {
+        let mut series = TimeSeries::new(width);
+        for (start, bucket) in &self.buckets {
+            series.add_bucket(width.bucket_start(*start), bucket);
+        }
+        series
+    }
+
+    fn merge_buckets(&mut self, other: &TimeSeries) {
+        for (start, theirs) in &other.buckets {
+            self.add_bucket(*start, theirs);
+        }
+    }
+
+    fn add_bucket(&mut self, start: i64, theirs: &Bucket) {
+        let bucket = match self.buckets.entry(start) {
+            Entry::Occupied(bucket) => bucket.into_mut(),
+            Entry::Vacant(slot) => match bucket_time(start) {
+                Some(time) => slot.insert(Bucket::empty(time)),
+                None => return,
+            },
+        };
+        bucket.total += theirs.total;
+        for (level, count) in &theirs.by_level {
+            *bucket.by_level.entry(*level).or_insert(0) += count;
+        }
+        for (component, count) in &theirs.by_component {
+            *bucket.by_component.entry(component.clone()).or_insert(0) += count;
+        }
+        for (component, count) in &theirs.errors_by_component {
+            *bucket.errors_by_component.entry(component.clone()).or_insert(0) += count;
+        }
+        bucket.add_samples(&theirs.samples);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buckets.is_empty()
+    }
+
+    // Every bucket from the first entry to the last, in order, including empty ones.
+    // Lazy, since a narrow width over a long span can mean millions of empty buckets.
+    pub fn buckets(&self) -> impl Iterator<Item = Bucket> + '_ {
+        self.bucket_slots()
+            .map(|(start, bucket)| bucket.cloned().unwrap_or_else(|| Bucket::empty(start)))
+    }
+
+    // Like buckets() without copying them: the start of every bucket in order,
+    // with the stored bucket, or None for an empty one.
+    pub fn bucket_slots(&self) -> impl Iterator<Item = (DateTime, Option<&Bucket>)> + '_ {
+        let range = match (self.buckets.keys().next(), self.buckets.keys().next_back()) {
+            (Some(&first), Some(&last)) => Some((first, last)),
+            _ => None,
+        };
+        let width = self.width.seconds;
+        range.into_iter().flat_map(move |(first, last)| {
+            (0..=(last - first) / width).filter_map(move |i| {
+                let start = first + i * width;
+                match self.buckets.get(&start) {
+                    Some(bucket) => Some((bucket.start.clone(), Some(bucket))),
+                    None => bucket_time(start).map(|time| (time, None)),
+                }
+            })
+        })
+    }
+
+    // Only the buckets that saw entries, in order.
+    pub fn non_empty_buckets(&self) -> impl Iterator<Item = &Bucket> {
+        self.buckets.values()
+    }
+
+    // The bucket with the most entries; the earliest one on a tie.
+    pub fn peak(&self) -> Option<&Bucket> {
+        self.buckets.values().rev().max_by_key(|bucket| bucket.total)
+    }
+}
+
+fn lcm(a: i64, b: i64) -> i64 {
+    let (mut x, mut y) = (a, b);
+    while y != 0 {
+        (x, y) = (y, x % y);
+    }
+    (a / x).saturating_mul(b)
+}
+
+// The start of a bucket as a UTC timestamp, or None outside the supported years.
+fn bucket_time(unix_seconds: i64) -> Option<DateTime> {
+    DateTime::from_unix_seconds(unix_seconds).ok()
+}
@@ -0,0 +1,173 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::{Serialize, Serializer};
+use walkdir::WalkDir;
+
+// Which files process_directory picks up. Patterns are globs matched against the
+// path relative to the directory being scanned, e.g. "*.log" or "archive/**", and
+// ignore case so APP.LOG is picked up like app.log.
+#[derive(Debug, Clone)]
+pub struct WalkOptions {
+    pub recursive: bool,
+    pub max_depth: Option<usize>, // levels below the root; only used when recursive
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub symlinks: SymlinkPolicy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    Skip,
+    Files, // read links to files, do not enter linked directories
+    All,
+}
+
+impl Default for WalkOptions {
+    fn default() -> Self {
+        WalkOptions {
+            recursive: false,
+            max_depth: None,
+            include: vec!["*.log".to_string(), "*.log.*".to_string()],
+            exclude: Vec::new(),
+            symlinks: SymlinkPolicy::Files,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SkipReason {
+    NotIncluded,
+    Excluded { pattern: String },
+    Symlink,
+    MaxDepth, // a directory that was not entered
+    Unreadable { reason: String },
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::NotIncluded => write!(f, "does not match any include pattern"),
+            SkipReason::Excluded { pattern } => write!(f, "matches exclude pattern '{}'", pattern),
+            SkipReason::Symlink => write!(f, "symbolic link (not following symlinks)"),
+            SkipReason::MaxDepth => write!(f, "directory below the maximum depth"),
+            SkipReason::Unreadable { reason } => write!(f, "unreadable: {}", reason),
+        }
+    }
+}
+
+// Serialized as its Display form, the way the text report prints it.
+impl Serialize for SkipReason {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedFile {
+    #[serde(serialize_with = "display_path")]
+    pub path: PathBuf,
+    pub reason: SkipReason,
+}
+
+// Paths that are not valid UTF-8 are written lossily rather than failing the report.
+fn display_path<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(&path.display())
+}
+
+// Files to process, in sorted order, and everything that was passed over.
+#[derive(Debug, Default)]
+pub struct WalkResult {
+    pub files: Vec<PathBuf>,
+    pub skipped: Vec<SkippedFile>,
+}
+
+fn build_globset(patterns: &[String]) -> Result<GlobSet, String> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern)
+            .case_insensitive(true)
+            .build()
+            .map_err(|e| format!("invalid glob '{}': {}", pattern, e))?;
+        builder.add(glob);
+    }
+    builder.build().map_err(|e| e.to_string())
+}
+
+// Walks `root` according to `options`. Fails only on an invalid glob pattern;
+// entries that cannot be read are reported as skipped.
+pub fn walk_directory(root: &Path, options: &WalkOptions) -> Result<WalkResult, String> {
+    let include = build_globset(&options.include)?;
+    let exclude = build_globset(&options.exclude)?;
+
+    let max_depth = if options.recursive {
+        options.max_depth.unwrap_or(usize::MAX).saturating_add(1)
+    } else {
+        1
+    };
+
+    let walker = WalkDir::new(root)
+        .min_depth(1)
+        .max_depth(max_depth)
+        .follow_links(options.symlinks == SymlinkPolicy::All)
+        .sort_by_file_name();
+
+    let mut result = WalkResult::default();
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                let path = e.path().map(Path::to_path_buf).unwrap_or_else(|| root.to_path_buf());
+                result.skipped.push(SkippedFile {
+                    path,
+                    reason: SkipReason::Unreadable { reason: e.to_string() },
+                });
+                continue;
+            }
+        };
+
+        let path = entry.path();
+        let file_type = entry.file_type();
+
+        if file_type.is_dir() {
+            if options.recursive && entry.depth() == max_depth {
+                result.skipped.push(SkippedFile { path: path.to_path_buf(), reason: SkipReason::MaxDepth });
+            }
+            continue;
+        }
+
+        // Only seen when links are not followed by the walker.
+        if file_type.is_symlink() {
+            let reason = match std::fs::metadata(path) {
+                _ if options.symlinks == SymlinkPolicy::Skip => Some(SkipReason::Symlink),
+                Ok(meta) if meta.is_file() => None,
+                Ok(_) => Some(SkipReason::Symlink),
+                Err(e) => Some(SkipReason::Unreadable { reason: e.to_string() }),
+            };
+            if let Some(reason) = reason {
+                result.skipped.push(SkippedFile { path: path.to_path_buf(), reason });
+                continue;
+            }
+        }
+
+        let relative = path.strip_prefix(root).unwrap_or(path);
+
+        if let Some(&idx) = exclude.matches(relative).first() {
+            result.skipped.push(SkippedFile {
+                path: path.to_path_buf(),
+                reason: SkipReason::Excluded { pattern: options.exclude[idx].clone() },
+            });
+            continue;
+        }
+
+        if !include.is_match(relative) {
+            result.skipped.push(SkippedFile { path: path.to_path_buf(), reason: SkipReason::NotIncluded });
+            continue;
+        }
+
+        result.files.push(path.to_path_buf());
+    }
+
+    Ok(result)
+}
@@ -1,9 +1,27 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use log_analyzer::date_time::DateTime;
 use log_analyzer::log_level::LogLevel;
-use log_analyzer::log_entry::{parse_log_line, LogEntry};
+use log_analyzer::log_entry::{parse_log_line, LogEntry, ParseErrorKind};
 use log_analyzer::statistics_aggregator::Statistics;
 use log_analyzer::log_analyzer::LogAnalyzer;
+use log_analyzer::diagnostics::render_diagnostics;
+use log_analyzer::walk::{SkipReason, SymlinkPolicy, WalkOptions};
+use log_analyzer::format_detector::FormatDetector;
+use log_analyzer::log_analyzer::{AnalyzerError, MAX_FOLLOW_FAILURES, MAX_OPEN_MERGE_INPUTS, STDIN_LABEL};
+use log_analyzer::multiline::EntryStart;
+use log_analyzer::rotation::sort_by_rotation;
+use log_analyzer::filter::{EntryFilter, MessageMatch};
+use log_analyzer::query::{Query, Value};
+use log_analyzer::timeseries::{BucketWidth, TimeSeries};
+use log_analyzer::report::render_timeline;
+use log_analyzer::templates::{message_template, TemplateMiner};
+use log_analyzer::anomaly::{detect_anomalies, AnomalyConfig, AnomalyKind};
+use log_analyzer::gaps::{find_gaps, ComponentActivity, GapConfig};
+use log_analyzer::date_time::{format_duration, parse_duration};
+use log_analyzer::diff::{compare, DiffConfig};
+use log_analyzer::snapshot::{load_snapshot, save_snapshot, SnapshotError};
+use log_analyzer::log_format::{format_by_name, JsonFieldNames, JsonFormat, LogFormat, LogfmtFormat, NginxAccessFormat, SyslogFormat};
 
 // ---------------- DATETIME TESTS ----------------
 
@@ -33,6 +51,76 @@ fn datetime_compare_two() {
     assert!(a < b);
 }
 
+#[test]
+fn datetime_parse_rfc3339_with_offset_and_fraction() {
+    let dt: DateTime = "2024-01-15T10:23:45.123+02:00".parse().unwrap();
+    assert_eq!(dt.nanosecond, 123_000_000);
+    assert_eq!(dt.offset_minutes, 120);
+    assert_eq!(dt.to_string(), "2024-01-15 10:23:45.123+02:00");
+
+    let utc: DateTime = "2024-01-15T08:23:45.123Z".parse().unwrap();
+    assert_eq!(dt, utc);
+    assert_eq!(dt.to_utc().hour, 8);
+
+    assert!("2024-01-15T10:23:45.1234567890Z".parse::<DateTime>().is_err());
+    assert!("2024-01-15T10:23:45+25:00".parse::<DateTime>().is_err());
+}
+
+#[test]
+fn datetime_compare_by_instant_across_zones() {
+    let a: DateTime = "2024-01-15T10:00:00+02:00".parse().unwrap();
+    let b: DateTime = "2024-01-15T09:30:00Z".parse().unwrap();
+    let c: DateTime = "2024-01-15 09:30:00.5".parse().unwrap();
+
+    assert!(a < b);
+    assert!(b < c);
+}
+
+#[test]
+fn datetime_reject_invalid_calendar_day() {
+    assert!("2024-02-31 10:00:00".parse::<DateTime>().is_err());
+    assert!("2023-02-29 10:00:00".parse::<DateTime>().is_err());
+    assert!("2024-04-31 10:00:00".parse::<DateTime>().is_err());
+    assert!("2024-02-29 10:00:00".parse::<DateTime>().is_ok());
+    assert!("2000-02-29 10:00:00".parse::<DateTime>().is_ok());
+    assert!("2100-02-29 10:00:00".parse::<DateTime>().is_err());
+}
+
+#[test]
+fn datetime_unix_epoch_round_trip() {
+    let dt: DateTime = "2024-01-15T10:23:45.123Z".parse().unwrap();
+    assert_eq!(dt.unix_seconds(), 1_705_314_225);
+    assert_eq!(dt.unix_millis(), 1_705_314_225_123);
+    assert_eq!(DateTime::from_unix_millis(1_705_314_225_123).unwrap(), dt);
+    assert_eq!(DateTime::from_unix_seconds(0).unwrap().to_string(), "1970-01-01 00:00:00");
+    assert!(DateTime::from_unix_seconds(-1).is_err());
+
+    let shifted: DateTime = "2024-01-15T12:23:45+02:00".parse().unwrap();
+    assert_eq!(shifted.unix_seconds(), 1_705_314_225);
+}
+
+#[test]
+fn datetime_duration_arithmetic() {
+    let a: DateTime = "2024-02-28 23:59:30".parse().unwrap();
+    let b = a.checked_add(Duration::from_secs(60)).unwrap();
+    assert_eq!(b.to_string(), "2024-02-29 00:00:30");
+    assert_eq!(b.duration_since(&a), Some(Duration::from_secs(60)));
+    assert_eq!(a.duration_since(&b), None);
+    assert_eq!(a.abs_diff(&b), Duration::from_secs(60));
+    assert_eq!(b.checked_sub(Duration::from_millis(500)).unwrap(), "2024-02-29 00:00:29.5".parse().unwrap());
+    assert!(a.checked_sub(Duration::from_secs(100 * 365 * 86_400)).is_none());
+}
+
+#[test]
+fn datetime_reject_instant_outside_supported_years() {
+    // In range as local time, but 1969 / 10000 in UTC
+    assert!("1970-01-01T00:10:00+01:00".parse::<DateTime>().is_err());
+    assert!("9999-12-31T23:59:59-01:00".parse::<DateTime>().is_err());
+    assert!("1970-01-01T00:10:00-01:00".parse::<DateTime>().is_ok());
+    assert!("9999-12-31T23:59:59+01:00".parse::<DateTime>().is_ok());
+    assert!(DateTime::from_unix_seconds(253_402_300_800).is_err());
+}
+
 // ---------------- LOG LEVEL TESTS ----------------
 
 #[test]
@@ -83,6 +171,269 @@ fn parser_malformed_line() {
     assert!(result.is_err());
 }
 
+#[test]
+fn parser_iso_timestamp_token() {
+    let line = "2024-01-15T10:23:45.123+02:00 [WARN] auth: Token expiring";
+    let entry = parse_log_line(line, Path::new("dummy.log"), 1).unwrap();
+    assert_eq!(entry.component, "auth");
+    assert_eq!(entry.message, "Token expiring");
+    assert_eq!(entry.timestamp.offset_minutes, 120);
+}
+
+#[test]
+fn parser_error_kind_and_span() {
+    let path = Path::new("dummy.log");
+
+    let err = parse_log_line("2024-01-15 10:00:00 [UNKNOWN] bad: Invalid level", path, 5).unwrap_err();
+    assert_eq!(err.kind, ParseErrorKind::UnknownLevel);
+    assert_eq!(&err.content[err.span.clone().unwrap()], "[UNKNOWN]");
+    assert_eq!(err.to_string(), "dummy.log:5: Invalid log level: Log Level is invalid");
+
+    let err = parse_log_line("2024-13-15 10:00:00 [INFO] app: x", path, 1).unwrap_err();
+    assert_eq!(err.kind, ParseErrorKind::BadTimestamp);
+    assert_eq!(&err.content[err.span.clone().unwrap()], "2024-13-15 10:00:00");
+
+    let err = parse_log_line("2024-01-15 10:00:00 [INFO] app", path, 1).unwrap_err();
+    assert_eq!(err.kind, ParseErrorKind::MalformedComponent);
+
+    let err = parse_log_line("2024-01-15 10:00:00 [INFO]", path, 1).unwrap_err();
+    assert_eq!(err.kind, ParseErrorKind::MissingComponent);
+    assert_eq!(err.span, Some(26..26));
+}
+
+#[test]
+fn analyzer_error_display_and_source() {
+    let mut analyzer = LogAnalyzer::new();
+    let err = analyzer.process_directory(Path::new("missing_dir")).unwrap_err();
+    assert_eq!(err.to_string(), "invalid path missing_dir: Path does not exist");
+
+    let io = AnalyzerError::IoError {
+        path: "a.log".into(),
+        source: std::io::Error::other("disk gone"),
+    };
+    assert_eq!(io.to_string(), "failed to read a.log: disk gone");
+    assert!(std::error::Error::source(&io).is_some());
+}
+
+// ---------------- LOG FORMAT TESTS ----------------
+
+#[test]
+fn format_logfmt_line() {
+    let line = r#"ts=2024-01-15T10:23:45Z level=warning component=storage msg="Disk \"sda\" slow" latency_ms=830"#;
+    let entry = LogfmtFormat.parse_line(line, Path::new("app.log"), 1).unwrap();
+    assert_eq!(entry.level, LogLevel::Warn);
+    assert_eq!(entry.component, "storage");
+    assert_eq!(entry.message, "Disk \"sda\" slow");
+    assert_eq!(entry.fields.get("latency_ms").map(String::as_str), Some("830"));
+
+    let err = LogfmtFormat.parse_line("level=info msg=hi", Path::new("app.log"), 2).unwrap_err();
+    assert_eq!(err.kind, ParseErrorKind::MissingTimestamp);
+
+    let line = r#"ts=2024-01-15T10:23:45Z level="very loud" msg=hi"#;
+    let err = LogfmtFormat.parse_line(line, Path::new("app.log"), 3).unwrap_err();
+    assert_eq!(err.kind, ParseErrorKind::UnknownLevel);
+    assert_eq!(&err.content[err.span.clone().unwrap()], r#"level="very loud""#);
+}
+
+#[test]
+fn format_json_lines() {
+    let path = Path::new("app.jsonl");
+    let line = r#"{"ts":"2024-01-15T10:23:45.5Z","level":"error","component":"storage","msg":"Mount failed","retries":3,"dev":"/dev/sda1"}"#;
+    let entry = JsonFormat::default().parse_line(line, path, 1).unwrap();
+    assert_eq!(entry.level, LogLevel::Error);
+    assert_eq!(entry.component, "storage");
+    assert_eq!(entry.fields.get("retries").map(String::as_str), Some("3"));
+    assert_eq!(entry.fields.get("dev").map(String::as_str), Some("/dev/sda1"));
+
+    let err = JsonFormat::default()
+        .parse_line(r#"{"ts":"2024-01-15T10:23:45Z","level":5,"component":"db","msg":"x"}"#, path, 2)
+        .unwrap_err();
+    assert_eq!(err.reason, "Type mismatch: log level key 'level' must be a string, got number");
+    assert_eq!(err.kind, ParseErrorKind::TypeMismatch);
+    assert_eq!(&err.content[err.span.clone().unwrap()], "5");
+
+    let custom = JsonFormat {
+        field_names: JsonFieldNames {
+            timestamp: "@timestamp".to_string(),
+            level: "severity".to_string(),
+            component: "logger".to_string(),
+            message: "message".to_string(),
+        },
+    };
+    let line = r#"{"@timestamp":"2024-01-15T10:23:45Z","severity":"WARNING","logger":"auth","message":"Retry"}"#;
+    assert_eq!(custom.parse_line(line, path, 3).unwrap().level, LogLevel::Warn);
+    let err = custom.parse_line(r#"{"@timestamp":"2024-01-15T10:23:45Z","severity":"info"}"#, path, 4).unwrap_err();
+    assert_eq!(err.reason, "Missing required field: component (key 'logger')");
+    assert_eq!(err.kind, ParseErrorKind::MissingComponent);
+}
+
+#[test]
+fn format_syslog_lines() {
+    let syslog = SyslogFormat::with_year(2024);
+    let path = Path::new("syslog");
+
+    let entry = syslog.parse_line("<11>Jan  5 10:23:45 web01 sshd[812]: Connection closed", path, 1).unwrap();
+    assert_eq!(entry.level, LogLevel::Error);
+    assert_eq!(entry.component, "sshd");
+    assert_eq!(entry.timestamp.to_string(), "2024-01-05 10:23:45");
+    assert_eq!(entry.fields.get("pid").map(String::as_str), Some("812"));
+
+    let entry = syslog
+        .parse_line("<165>1 2024-01-15T10:23:45.003Z web01 evntslog - ID47 [meta x=\"1\"] Restarted", path, 2)
+        .unwrap();
+    assert_eq!(entry.level, LogLevel::Info);
+    assert_eq!(entry.component, "evntslog");
+    assert_eq!(entry.message, "Restarted");
+}
+
+#[test]
+fn format_syslog_year_from_file_modification_time() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("syslog");
+    let file = std::fs::File::create(&path).unwrap();
+    // 2021-06-01 00:00:00 UTC
+    file.set_modified(std::time::UNIX_EPOCH + Duration::from_secs(1_622_505_600)).unwrap();
+
+    let line = "<11>Jan  5 10:23:45 web01 sshd[812]: Connection closed";
+    let entry = SyslogFormat::default().parse_line(line, &path, 1).unwrap();
+    assert_eq!(entry.timestamp.to_string(), "2021-01-05 10:23:45");
+    // A configured year wins; without one, input with no modification time is rejected
+    let entry = SyslogFormat::with_year(2024).parse_line(line, &path, 1).unwrap();
+    assert_eq!(entry.timestamp.year, 2024);
+    let err = SyslogFormat::default().parse_line(line, Path::new(STDIN_LABEL), 1).unwrap_err();
+    assert_eq!(err.kind, ParseErrorKind::BadTimestamp);
+}
+
+#[test]
+fn format_syslog_file_crossing_new_year() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("syslog");
+    let file = std::fs::File::create(&path).unwrap();
+    // 2022-01-02 00:00:00 UTC
+    file.set_modified(std::time::UNIX_EPOCH + Duration::from_secs(1_641_081_600)).unwrap();
+
+    let format = SyslogFormat::default();
+    let december = format.parse_line("<14>Dec 31 23:59:59 web01 cron: Rotating", &path, 1).unwrap();
+    assert_eq!(december.timestamp.to_string(), "2021-12-31 23:59:59");
+    let january = format.parse_line("<14>Jan  1 08:00:00 web01 cron: Rotated", &path, 2).unwrap();
+    assert_eq!(january.timestamp.to_string(), "2022-01-01 08:00:00");
+    // A configured year is taken as is
+    let entry = SyslogFormat::with_year(2022).parse_line("<14>Dec 31 23:59:59 web01 cron: Rotating", &path, 1).unwrap();
+    assert_eq!(entry.timestamp.year, 2022);
+}
+
+#[test]
+fn format_nginx_access_line() {
+    let line = r#"10.0.0.7 - bob [15/Jan/2024:10:23:45 +0100] "GET /api/v1/users HTTP/1.1" 503 0 "-" "curl/8.0""#;
+    let entry = NginxAccessFormat::default().parse_line(line, Path::new("access.log"), 1).unwrap();
+    assert_eq!(entry.level, LogLevel::Error);
+    assert_eq!(entry.component, "nginx");
+    assert_eq!(entry.timestamp.to_utc().hour, 9);
+    assert_eq!(entry.fields.get("path").map(String::as_str), Some("/api/v1/users"));
+    assert_eq!(entry.fields.get("user_agent").map(String::as_str), Some("curl/8.0"));
+}
+
+#[test]
+fn analyzer_with_configured_format() {
+    let mut analyzer = LogAnalyzer::new().with_format(format_by_name("bracketed").unwrap());
+    assert_eq!(analyzer.process_file(Path::new("tests/fixtures/simple.log")).unwrap(), 5);
+
+    let mut analyzer = LogAnalyzer::new().with_format(format_by_name("logfmt").unwrap());
+    assert_eq!(analyzer.process_file(Path::new("tests/fixtures/simple.log")).unwrap(), 0);
+    assert!(format_by_name("xml").is_none());
+}
+
+#[test]
+fn detection_picks_format_per_file() {
+    let mut analyzer = LogAnalyzer::new().with_format_detection(FormatDetector::default());
+    assert_eq!(analyzer.process_file(Path::new("tests/fixtures/formats/service.logfmt.log")).unwrap(), 4);
+    assert_eq!(analyzer.process_file(Path::new("tests/fixtures/formats/messages.syslog.log")).unwrap(), 3);
+    assert_eq!(analyzer.process_file(Path::new("tests/fixtures/simple.log")).unwrap(), 5);
+    assert_eq!(analyzer.process_file(Path::new("tests/fixtures/formats/events.jsonl")).unwrap(), 3);
+
+    let detected: Vec<&str> = analyzer.detected_formats().iter().map(|d| d.format.as_str()).collect();
+    assert_eq!(detected, vec!["logfmt", "syslog", "bracketed", "json"]);
+    assert!((analyzer.detected_formats()[0].confidence - 0.8).abs() < 1e-9);
+    assert_eq!(analyzer.parse_errors().len(), 1);
+    assert_eq!(analyzer.parse_errors()[0].line_number, 4);
+}
+
+#[test]
+fn detection_fails_when_nothing_matches() {
+    let mut analyzer = LogAnalyzer::new().with_format_detection(FormatDetector::default());
+    let result = analyzer.process_file(Path::new("tests/fixtures/formats/unknown.txt"));
+    assert!(matches!(result, Err(AnalyzerError::UnknownFormat { confidence, .. }) if confidence == 0.0));
+    assert!(analyzer.parse_errors().is_empty());
+    assert!(analyzer.detected_formats().is_empty());
+}
+
+#[test]
+fn detection_failure_of_every_input_exits_with_an_error() {
+    let run = |paths: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_log-analyzer"))
+            .args(paths)
+            .args(["--input-format", "auto"])
+            .output()
+            .unwrap()
+    };
+
+    let output = run(&["tests/fixtures/formats/unknown.txt"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!output.status.success());
+    assert!(stdout.contains("could not detect the log format"), "{}", stdout);
+    assert!(!stdout.contains("LOG ANALYSIS REPORT"), "{}", stdout);
+
+    // One readable input is enough for a report
+    let output = run(&["tests/fixtures/formats/unknown.txt", "tests/fixtures/simple.log"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("LOG ANALYSIS REPORT"));
+}
+
+#[test]
+fn multiline_joins_stack_trace() {
+    let path = Path::new("tests/fixtures/formats/stacktrace.txt");
+
+    let mut analyzer = LogAnalyzer::new().with_multiline(EntryStart::date_prefix());
+    assert_eq!(analyzer.process_file(path).unwrap(), 3);
+    let entries = analyzer.entries();
+    assert_eq!(entries.len(), 3);
+    assert_eq!(
+        entries[1].message,
+        "Unhandled exception\njava.lang.NullPointerException: user was null\n    at com.example.UserService.load(UserService.java:42)\n    at com.example.Api.handle(Api.java:17)"
+    );
+    // Only the bad header is an error, not its continuation line
+    assert_eq!(analyzer.parse_errors().len(), 1);
+    assert_eq!(analyzer.parse_errors()[0].line_number, 7);
+
+    let mut analyzer = LogAnalyzer::new().with_multiline(EntryStart::Parseable);
+    assert_eq!(analyzer.process_file(path).unwrap(), 3);
+    assert!(analyzer.parse_errors().is_empty());
+    assert!(analyzer.entries()[2].message.ends_with("Hidden.java:1)"));
+}
+
+#[test]
+fn multiline_with_format_detection_scores_entry_starts() {
+    let path = Path::new("tests/fixtures/formats/stacktrace.txt");
+
+    // Stack trace frames are not scored, only the lines starting an entry
+    let mut analyzer = LogAnalyzer::new()
+        .with_format_detection(FormatDetector::default())
+        .with_multiline(EntryStart::date_prefix());
+    assert_eq!(analyzer.process_file(path).unwrap(), 3);
+    assert_eq!(analyzer.detected_formats()[0].format, "bracketed");
+    assert!((analyzer.detected_formats()[0].confidence - 0.75).abs() < 1e-9);
+
+    let mut analyzer = LogAnalyzer::new()
+        .with_multiline(EntryStart::Parseable)
+        .with_format_detection(FormatDetector::default());
+    assert_eq!(analyzer.process_file(path).unwrap(), 3);
+    assert_eq!(analyzer.detected_formats()[0].confidence, 1.0);
+
+    // Without the multi-line rule the same sample is rejected
+    let mut analyzer = LogAnalyzer::new().with_format_detection(FormatDetector::default());
+    assert!(matches!(analyzer.process_file(path), Err(AnalyzerError::UnknownFormat { .. })));
+}
+
 // ---------------- STATISTICS TESTS ----------------
 
 #[test]
@@ -140,6 +491,44 @@ fn statistics_percentages() {
     assert!(stats.error_rate > 0.4 && stats.error_rate < 0.6);
 }
 
+#[test]
+fn statistics_merge_matches_single_pass() {
+    let mut analyzer = LogAnalyzer::new();
+    analyzer.process_file(Path::new("tests/fixtures/simple.log")).unwrap();
+    analyzer.process_file(Path::new("tests/fixtures/tree/host1/2024-01-15/app.log")).unwrap();
+    let entries = analyzer.entries();
+
+    let single = Statistics::from_entries(entries);
+    for split in 0..=entries.len() {
+        let mut merged = Statistics::from_entries(&entries[..split]);
+        merged.merge(&Statistics::from_entries(&entries[split..]));
+        assert_eq!(merged, single);
+
+        let mut reversed = Statistics::from_entries(&entries[split..]);
+        reversed.merge(&Statistics::from_entries(&entries[..split]));
+        assert_eq!(reversed, single);
+    }
+}
+
+#[test]
+fn statistics_ties_are_deterministic() {
+    let path = Path::new("dummy.log");
+    let lines = [
+        "2024-01-15 11:00:00 [INFO] zeta: a",
+        "2024-01-15 10:00:00 [INFO] alpha: b",
+    ];
+    let entries: Vec<LogEntry> = lines.iter().map(|l| parse_log_line(l, path, 1).unwrap()).collect();
+
+    let stats = Statistics::from_entries(&entries);
+    assert_eq!(stats.most_active_component.as_deref(), Some("alpha"));
+    assert_eq!(stats.peak_hour, Some(10));
+
+    let mut merged = Statistics::new();
+    merged.merge(&Statistics::from_entries(&entries[1..]));
+    merged.merge(&Statistics::from_entries(&entries[..1]));
+    assert_eq!(merged, stats);
+}
+
 // ---------------- FILE PROCESSING TESTS ----------------
 #[test]
 fn file_process_valid_file() {
@@ -167,3 +556,1004 @@ fn file_skip_malformed_lines() {
     let result = analyzer.process_directory(path);
     assert!(result.is_ok());
 }
+
+#[test]
+fn file_invalid_utf8_is_per_line_error() {
+    let mut analyzer = LogAnalyzer::new();
+    let path = Path::new("tests/fixtures/invalid_utf8.log");
+
+    let result = analyzer.process_file(path);
+    assert_eq!(result.unwrap(), 2);
+    assert_eq!(analyzer.parse_errors().len(), 1);
+    assert_eq!(analyzer.parse_errors()[0].line_number, 2);
+    assert!(analyzer.parse_errors()[0].content.contains('\u{FFFD}'));
+}
+
+#[test]
+fn directory_top_level_only_by_default() {
+    let mut analyzer = LogAnalyzer::new();
+    assert_eq!(analyzer.process_directory(Path::new("tests/fixtures/tree")).unwrap(), 1);
+    let skipped = analyzer.skipped_files();
+    assert_eq!(skipped.len(), 1);
+    assert!(skipped[0].path.ends_with("notes.txt"));
+    assert_eq!(skipped[0].reason, SkipReason::NotIncluded);
+}
+
+#[test]
+fn directory_recursive_with_globs_and_depth() {
+    let walk = WalkOptions {
+        recursive: true,
+        exclude: vec!["archive/**".to_string()],
+        ..WalkOptions::default()
+    };
+    let mut analyzer = LogAnalyzer::new().with_walk_options(walk.clone());
+    assert_eq!(analyzer.process_directory(Path::new("tests/fixtures/tree")).unwrap(), 5);
+    assert!(analyzer.skipped_files().iter().any(|s| {
+        s.path.ends_with("archive/old.log") && s.reason == SkipReason::Excluded { pattern: "archive/**".to_string() }
+    }));
+
+    let mut analyzer = LogAnalyzer::new().with_walk_options(WalkOptions { max_depth: Some(1), ..walk });
+    assert_eq!(analyzer.process_directory(Path::new("tests/fixtures/tree")).unwrap(), 3);
+    assert!(analyzer.skipped_files().iter().any(|s| {
+        s.path.ends_with("host1/2024-01-15") && s.reason == SkipReason::MaxDepth
+    }));
+
+    let bad = WalkOptions { include: vec!["[".to_string()], ..WalkOptions::default() };
+    let result = LogAnalyzer::new().with_walk_options(bad).process_directory(Path::new("tests/fixtures/tree"));
+    assert!(matches!(result, Err(AnalyzerError::InvalidPattern { .. })));
+}
+
+#[cfg(unix)]
+#[test]
+fn directory_symlinks_follow_choice() {
+    let dir = tempfile::tempdir().unwrap();
+    let target = std::fs::canonicalize("tests/fixtures/simple.log").unwrap();
+    std::os::unix::fs::symlink(&target, dir.path().join("current.log")).unwrap();
+    std::os::unix::fs::symlink(target.parent().unwrap().join("tree"), dir.path().join("linked")).unwrap();
+
+    let mut analyzer = LogAnalyzer::new();
+    assert_eq!(analyzer.process_directory(dir.path()).unwrap(), 5);
+    assert_eq!(analyzer.skipped_files().len(), 1);
+    assert!(analyzer.skipped_files()[0].path.ends_with("linked"));
+
+    let walk = WalkOptions { symlinks: SymlinkPolicy::Skip, ..WalkOptions::default() };
+    let mut analyzer = LogAnalyzer::new().with_walk_options(walk);
+    assert!(matches!(analyzer.process_directory(dir.path()), Err(AnalyzerError::NoFilesFound { .. })));
+    assert!(analyzer.skipped_files().iter().all(|s| s.reason == SkipReason::Symlink));
+
+    let walk = WalkOptions { recursive: true, symlinks: SymlinkPolicy::All, ..WalkOptions::default() };
+    let mut analyzer = LogAnalyzer::new().with_walk_options(walk);
+    assert!(analyzer.process_directory(dir.path()).unwrap() > 5);
+}
+
+#[test]
+fn directory_default_globs_ignore_case() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::copy("tests/fixtures/simple.log", dir.path().join("APP.LOG")).unwrap();
+
+    let mut analyzer = LogAnalyzer::new();
+    assert_eq!(analyzer.process_directory(dir.path()).unwrap(), 5);
+    assert!(analyzer.skipped_files().is_empty());
+}
+
+#[test]
+fn file_compressed_inputs_are_decompressed() {
+    use std::io::Write;
+
+    let plain = std::fs::read("tests/fixtures/simple.log").unwrap();
+    let dir = tempfile::tempdir().unwrap();
+
+    let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gz.write_all(&plain).unwrap();
+    std::fs::write(dir.path().join("app.log.1.gz"), gz.finish().unwrap()).unwrap();
+
+    std::fs::write(dir.path().join("app.log.2.zst"), zstd::encode_all(&plain[..], 3).unwrap()).unwrap();
+
+    let mut bz = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+    bz.write_all(&plain).unwrap();
+    // No telling extension: detected from the magic bytes
+    std::fs::write(dir.path().join("app.log.3"), bz.finish().unwrap()).unwrap();
+
+    let mut analyzer = LogAnalyzer::new();
+    assert_eq!(analyzer.process_file(&dir.path().join("app.log.1.gz")).unwrap(), 5);
+
+    let mut analyzer = LogAnalyzer::new();
+    assert_eq!(analyzer.process_directory(dir.path()).unwrap(), 15);
+    assert!(analyzer.parse_errors().is_empty());
+    assert_eq!(analyzer.entries()[0].message, "Application started");
+}
+
+#[test]
+fn rotation_orders_oldest_first() {
+    let mut files: Vec<PathBuf> = ["app.log", "app.log.1", "app.log.10", "app.log.2.gz", "app.log.2024-01-14", "app-20240113.log"]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+    sort_by_rotation(&mut files);
+
+    let names: Vec<_> = files.iter().map(|p| p.to_str().unwrap()).collect();
+    assert_eq!(names, ["app-20240113.log", "app.log.2024-01-14", "app.log.10", "app.log.2.gz", "app.log.1", "app.log"]);
+}
+
+#[test]
+fn merge_rotated_files_chronologically() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("app.log"), "2024-01-15 10:00:05 [INFO] api: c\n2024-01-15 10:00:09 [INFO] api: f\n").unwrap();
+    std::fs::write(dir.path().join("app.log.1"), "2024-01-15 10:00:02 [INFO] db: b\n2024-01-15 10:00:07 [INFO] db: e\n").unwrap();
+    std::fs::write(dir.path().join("app.log.2"), "2024-01-15 10:00:01 [INFO] db: a\nbroken\n2024-01-15 10:00:05 [INFO] db: d\n").unwrap();
+
+    let mut analyzer = LogAnalyzer::new().with_chronological_merge(true);
+    assert_eq!(analyzer.process_directory(dir.path()).unwrap(), 6);
+    assert_eq!(analyzer.parse_errors().len(), 1);
+
+    let messages: Vec<_> = analyzer.entries().iter().map(|e| e.message.as_str()).collect();
+    // Equal timestamps keep rotation order: app.log.2 is older than app.log
+    assert_eq!(messages, ["a", "b", "d", "c", "e", "f"]);
+
+}
+
+#[test]
+fn merge_more_files_than_can_be_open_at_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let count = MAX_OPEN_MERGE_INPUTS * 2 + 50;
+    let files: Vec<PathBuf> = (0..count)
+        .map(|i| {
+            let path = dir.path().join(format!("many{:03}.log", i));
+            let text = format!(
+                "2024-01-15 10:{:02}:{:02} [INFO] api: early {i}\n2024-01-15 11:00:00 [INFO] api: tie {i}\n",
+                i % 60,
+                i / 60
+            );
+            std::fs::write(&path, text).unwrap();
+            path
+        })
+        .collect();
+
+    // Merged in groups through temporary runs, with the same result as one merge
+    let mut analyzer = LogAnalyzer::new();
+    assert_eq!(analyzer.process_files_merged(&files).unwrap(), count * 2);
+    let entries = analyzer.entries();
+    assert!(entries.windows(2).all(|pair| pair[0].timestamp <= pair[1].timestamp));
+    assert_eq!(entries[0].message, "early 0");
+    assert_eq!(entries[0].source_file, files[0]);
+    // Equal timestamps keep file order across the groups
+    let ties: Vec<String> = entries[count..].iter().map(|e| e.message.clone()).collect();
+    let expected: Vec<String> = (0..count).map(|i| format!("tie {}", i)).collect();
+    assert_eq!(ties, expected);
+}
+
+#[test]
+fn parallel_matches_sequential() {
+    let dir = tempfile::tempdir().unwrap();
+    for i in 0..12 {
+        let mut text = String::new();
+        for j in 0..(i * 7) % 30 + 1 {
+            let level = ["INFO", "WARN", "ERROR", "BOGUS"][(i + j) % 4];
+            text.push_str(&format!("2024-01-15 {:02}:{:02}:00 [{}] svc{}: message {}\n", j % 24, i, level, j % 3, j));
+            if j % 5 == 0 {
+                text.push_str("    at some.Frame(File.java:1)\n");
+            }
+        }
+        std::fs::write(dir.path().join(format!("app{:02}.log", i)), text).unwrap();
+    }
+
+    let run = |workers| {
+        let mut analyzer = LogAnalyzer::new().with_multiline(EntryStart::date_prefix()).with_workers(workers);
+        let count = analyzer.process_directory(dir.path()).unwrap();
+        (count, analyzer)
+    };
+    let (seq_count, seq) = run(1);
+    let (par_count, par) = run(4);
+
+    assert_eq!(seq_count, par_count);
+    assert_eq!(format!("{:?}", seq.entries()), format!("{:?}", par.entries()));
+    assert_eq!(format!("{:?}", seq.parse_errors()), format!("{:?}", par.parse_errors()));
+    assert!(!par.parse_errors().is_empty());
+
+    let (a, b) = (seq.statistics(), par.statistics());
+    assert_eq!(a.total_entries, b.total_entries);
+    assert_eq!(a.entries_by_level, b.entries_by_level);
+    assert_eq!(a.entries_by_component, b.entries_by_component);
+    assert_eq!(a.most_active_component, b.most_active_component);
+    assert_eq!(a.peak_hour, b.peak_hour);
+}
+
+#[test]
+fn statistics_only_drops_entries_and_caps_errors() {
+    let full = {
+        let mut analyzer = LogAnalyzer::new();
+        analyzer.process_directory(Path::new("tests/fixtures")).unwrap();
+        analyzer
+    };
+
+    for workers in [1, 3] {
+        let mut analyzer = LogAnalyzer::new().with_statistics_only(true).with_max_parse_errors(2).with_workers(workers);
+        let count = analyzer.process_directory(Path::new("tests/fixtures")).unwrap();
+
+        assert_eq!(count, full.entries().len());
+        assert!(analyzer.entries().is_empty());
+        assert_eq!(analyzer.statistics(), full.statistics());
+        assert_eq!(analyzer.parse_error_count(), full.parse_errors().len());
+        assert_eq!(format!("{:?}", analyzer.parse_errors()), format!("{:?}", &full.parse_errors()[..2]));
+    }
+}
+
+#[test]
+fn reader_input_gets_label_and_mixes_with_files() {
+    let input = std::fs::read("tests/fixtures/simple.log").unwrap();
+    let mut analyzer = LogAnalyzer::new();
+    assert_eq!(analyzer.process_reader(std::io::Cursor::new(input), Path::new(STDIN_LABEL)).unwrap(), 5);
+    assert_eq!(analyzer.process_file(Path::new("tests/fixtures/simple.log")).unwrap(), 5);
+
+    assert_eq!(analyzer.entries()[0].source_file, Path::new("<stdin>"));
+    assert_eq!(analyzer.entries()[5].source_file, Path::new("tests/fixtures/simple.log"));
+    assert_eq!(analyzer.statistics().total_entries, 10);
+}
+
+#[cfg(unix)]
+#[test]
+fn file_named_pipe_is_read() {
+    use std::io::Write;
+
+    let dir = tempfile::tempdir().unwrap();
+    let fifo = dir.path().join("pipe.log");
+    assert!(std::process::Command::new("mkfifo").arg(&fifo).status().unwrap().success());
+
+    let writer = {
+        let fifo = fifo.clone();
+        std::thread::spawn(move || {
+            let mut pipe = std::fs::OpenOptions::new().write(true).open(fifo).unwrap();
+            pipe.write_all(b"2024-01-15 10:00:00 [INFO] api: via pipe\n").unwrap();
+        })
+    };
+
+    let mut analyzer = LogAnalyzer::new();
+    assert_eq!(analyzer.process_file(&fifo).unwrap(), 1);
+    writer.join().unwrap();
+    assert_eq!(analyzer.entries()[0].message, "via pipe");
+}
+
+#[test]
+fn follow_appends_rotation_and_truncation() {
+    use std::io::Write;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("app.log");
+    let append = |text: &str| {
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&path).unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    };
+
+    let mut analyzer = LogAnalyzer::new();
+    analyzer.follow(&path).unwrap();
+    // Not created yet
+    assert_eq!(analyzer.poll().entries, 0);
+
+    append("2024-01-15 10:00:00 [INFO] api: one\n2024-01-15 10:00:01 [ERROR] db: tw");
+    assert_eq!(analyzer.poll().entries, 1);
+    append("o\n");
+    assert_eq!(analyzer.poll().entries, 1);
+    assert_eq!(analyzer.entries()[1].message, "two");
+    assert_eq!(analyzer.statistics().error_count, 1);
+
+    // Rotated: the old file is renamed and a new one takes its place
+    std::fs::rename(&path, dir.path().join("app.log.1")).unwrap();
+    append("2024-01-15 11:00:00 [INFO] api: three\n");
+    assert_eq!(analyzer.poll().entries, 1);
+
+    // Truncated in place
+    std::fs::write(&path, "").unwrap();
+    assert_eq!(analyzer.poll().entries, 0);
+    append("2024-01-15 11:00:01 [WARN] api: four\n");
+    assert_eq!(analyzer.poll().entries, 1);
+
+    let stats = analyzer.statistics();
+    assert_eq!(stats.total_entries, 4);
+    assert_eq!(stats.most_active_component.as_deref(), Some("api"));
+    assert_eq!(stats.entries_by_component["api"], 3);
+}
+
+#[test]
+fn follow_waits_before_taking_a_partial_line_as_complete() {
+    use std::io::Write;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("app.log");
+    let append = |text: &str| {
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&path).unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    };
+
+    // A slow writer's line is not split, however many polls it takes
+    let mut analyzer = LogAnalyzer::new();
+    analyzer.follow(&path).unwrap();
+    append("2024-01-15 10:00:00 [INFO] api: slow");
+    assert_eq!(analyzer.poll().entries, 0);
+    assert_eq!(analyzer.poll().entries, 0);
+    append(" writer\n2024-01-15 10:00:01 [INFO] api: cut");
+    assert_eq!(analyzer.poll().entries, 1);
+    assert_eq!(analyzer.entries()[0].message, "slow writer");
+    // Truncation finishes the old content, including its unterminated line
+    std::fs::write(&path, "").unwrap();
+    assert_eq!(analyzer.poll().entries, 1);
+    assert_eq!(analyzer.entries()[1].message, "cut");
+
+    // Once the grace period has passed without growth, the line is complete
+    let other = dir.path().join("other.log");
+    std::fs::write(&other, "2024-01-15 10:00:02 [INFO] api: no newline").unwrap();
+    let mut analyzer = LogAnalyzer::new().with_partial_line_grace(Duration::ZERO);
+    analyzer.follow(&other).unwrap();
+    assert_eq!(analyzer.poll().entries, 0);
+    assert_eq!(analyzer.poll().entries, 1);
+    assert_eq!(analyzer.entries()[0].message, "no newline");
+}
+
+#[test]
+fn follow_detects_the_format_once_enough_lines_arrived() {
+    use std::io::Write;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("app.log");
+    let append = |text: &str| {
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&path).unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    };
+
+    // A banner line alone would fail detection; the lines after it settle the format
+    let detector = FormatDetector::default().with_sample_lines(4);
+    let mut analyzer = LogAnalyzer::new().with_format_detection(detector.clone());
+    analyzer.follow(&path).unwrap();
+    append("=== service starting ===\nts=2024-01-15T10:00:00Z level=info msg=one\n");
+    let outcome = analyzer.poll();
+    assert_eq!(outcome.entries, 0);
+    assert!(outcome.errors.is_empty());
+    append("ts=2024-01-15T10:00:01Z level=warn msg=two\nts=2024-01-15T10:00:02Z level=info msg=three\nts=2024-01-15T10:00:03Z level=error msg=four\n");
+    assert_eq!(analyzer.poll().entries, 4);
+    assert_eq!(analyzer.detected_formats()[0].format, "logfmt");
+    assert_eq!(analyzer.parse_error_count(), 1);
+
+    // A file that stays short is detected once the grace period has passed
+    let other = dir.path().join("other.log");
+    std::fs::write(&other, "ts=2024-01-15T10:00:00Z level=info msg=one\n").unwrap();
+    let mut analyzer = LogAnalyzer::new()
+        .with_format_detection(detector)
+        .with_partial_line_grace(Duration::ZERO);
+    analyzer.follow(&other).unwrap();
+    assert_eq!(analyzer.poll().entries, 1);
+    assert_eq!(analyzer.detected_formats()[0].format, "logfmt");
+}
+
+#[test]
+fn follow_keeps_polling_other_files_when_one_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    let good = dir.path().join("app.log");
+    std::fs::write(&good, "2024-01-15 10:00:00 [INFO] api: one\n").unwrap();
+    // A path under a regular file can never be opened
+    let blocker = dir.path().join("blocker");
+    std::fs::write(&blocker, "").unwrap();
+    let broken = blocker.join("app.log");
+
+    let mut analyzer = LogAnalyzer::new();
+    analyzer.follow(&broken).unwrap();
+    analyzer.follow(&good).unwrap();
+
+    let outcome = analyzer.poll();
+    assert_eq!(outcome.entries, 1);
+    assert!(matches!(&outcome.errors[..], [AnalyzerError::IoError { path, .. }] if *path == broken));
+    assert!(outcome.stopped.is_empty());
+
+    // Given up on after failing every poll, while the other file is still followed
+    for _ in 1..MAX_FOLLOW_FAILURES {
+        let outcome = analyzer.poll();
+        assert_eq!(outcome.errors.len(), 1);
+        assert_eq!(analyzer.followed_count(), 2 - outcome.stopped.len());
+    }
+    assert_eq!(analyzer.followed_count(), 1);
+    let outcome = analyzer.poll();
+    assert!(outcome.errors.is_empty());
+}
+
+#[test]
+fn follow_rejects_unrepresentable_interval() {
+    for interval in ["inf", "1e300"] {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_log-analyzer"))
+            .args(["tests/fixtures/simple.log", "--follow", "--interval", interval])
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.starts_with("Invalid --interval"), "{}", stdout);
+    }
+}
+
+#[test]
+fn follow_rejects_options_that_need_a_finished_run() {
+    for extra in [["--query", "SELECT count()"], ["--save-snapshot", "unused.json"]] {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_log-analyzer"))
+            .args(["tests/fixtures/simple.log", "--follow"])
+            .args(extra)
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.starts_with("--follow cannot be combined"), "{}", stdout);
+    }
+}
+
+// ---------------- FILTER TESTS ----------------
+
+#[test]
+fn filter_level_component_time_and_message() {
+    let filter = EntryFilter::new()
+        .with_min_level(LogLevel::Warn)
+        .with_component("storage")
+        .with_component("auth")
+        .with_time_range(Some("2024-01-15 10:00:00".parse().unwrap()), Some("2024-01-15 11:00:00".parse().unwrap()));
+
+    let path = Path::new("dummy.log");
+    let keep = |line: &str, filter: &EntryFilter| filter.matches(&parse_log_line(line, path, 1).unwrap());
+    assert!(keep("2024-01-15 10:30:00 [ERROR] storage: Disk full", &filter));
+    assert!(!keep("2024-01-15 10:30:00 [INFO] storage: Disk ok", &filter));
+    assert!(!keep("2024-01-15 10:30:00 [ERROR] network: Down", &filter));
+    assert!(!keep("2024-01-15 11:00:00 [ERROR] auth: Late", &filter));
+
+    let filter = filter.with_message(MessageMatch::Regex(regex::Regex::new(r"(?i)disk \w+").unwrap()));
+    assert!(keep("2024-01-15 10:30:00 [WARN] storage: DISK slow", &filter));
+    assert!(!keep("2024-01-15 10:30:00 [WARN] auth: Token expired", &filter));
+}
+
+#[test]
+fn filter_applies_during_ingestion() {
+    let filter = EntryFilter::new().without_component("config").with_message(MessageMatch::Contains("i".to_string()));
+    for workers in [1, 2] {
+        let mut analyzer = LogAnalyzer::new().with_filter(filter.clone()).with_workers(workers).with_statistics_only(workers == 2);
+        let count = analyzer.process_directory(Path::new("tests/fixtures")).unwrap();
+
+        let stats = analyzer.statistics();
+        assert_eq!(count, stats.total_entries);
+        assert!(!stats.entries_by_component.contains_key("config"));
+        assert_eq!(count + analyzer.filtered_count(), 9);
+    }
+}
+
+// ---------------- QUERY TESTS ----------------
+
+#[test]
+fn query_group_order_limit() {
+    let mut analyzer = LogAnalyzer::new();
+    analyzer.process_directory(Path::new("tests/fixtures")).unwrap();
+
+    let query: Query = "select component, count(), max(timestamp) as last where level >= info and not component in (config) \
+                        group by component order by count desc, component limit 2"
+        .parse()
+        .unwrap();
+    let result = query.run(analyzer.entries());
+    assert_eq!(result.columns, ["component", "count", "last"]);
+    assert_eq!(result.rows.len(), 2);
+    assert_eq!(result.rows[0][0], Value::Text("app".to_string()));
+    assert_eq!(result.rows[0][1], Value::Number(4));
+    assert_eq!(result.rows[1][0], Value::Text("network".to_string()));
+
+    let json = result.to_json();
+    assert_eq!(json[0]["count"], 4);
+    assert_eq!(json[0]["last"], "2024-01-15 10:00:02");
+    assert!(result.render_table().starts_with("component  count  last\n---------  -----  "));
+
+    let result = "SELECT message WHERE message MATCHES '^Listen'".parse::<Query>().unwrap().run(analyzer.entries());
+    assert_eq!(result.rows, [[Value::Text("Listening on port 8080".to_string())]]);
+}
+
+#[test]
+fn query_errors_point_at_the_problem() {
+    let err = "SELECT level, count() WHERE level >= loud".parse::<Query>().unwrap_err();
+    assert_eq!(err.column, 38);
+    assert!(err.message.contains("not a log level"));
+
+    let err = "SELECT message, count() GROUP BY level".parse::<Query>().unwrap_err();
+    assert_eq!(err.to_string(), "'message' must appear in GROUP BY or inside an aggregate (at column 8)");
+
+    assert!("SELECT component WHERE message MATCHES '('".parse::<Query>().unwrap_err().message.starts_with("invalid regex"));
+    assert!("SELECT component ORDER BY level".parse::<Query>().is_err());
+    assert!("SELECT avg(level)".parse::<Query>().unwrap_err().message.contains("unknown function"));
+}
+
+// ---------------- TIMELINE TESTS ----------------
+
+#[test]
+fn timeline_buckets_by_absolute_time_with_zero_fill() {
+    let path = Path::new("dummy.log");
+    let lines = [
+        "2024-01-15 10:00:10 [ERROR] db: a",
+        "2024-01-15 10:04:59 [INFO] app: b",
+        "2024-01-15 10:15:00 [INFO] app: c",
+        "2024-01-16 10:00:00 [INFO] app: d",
+    ];
+    let mut series = TimeSeries::new("5m".parse().unwrap());
+    for (i, line) in lines.iter().enumerate() {
+        series.add(&parse_log_line(line, path, i + 1).unwrap());
+    }
+
+    let buckets: Vec<_> = series.buckets().collect();
+    // 10:00 through 10:00 the next day, every five minutes
+    assert_eq!(buckets.len(), 24 * 12 + 1);
+    assert_eq!(buckets[0].start, "2024-01-15 10:00:00".parse::<DateTime>().unwrap());
+    assert_eq!((buckets[0].total, buckets[0].errors()), (2, 1));
+    assert_eq!(buckets[0].by_component.get("db"), Some(&1));
+    assert_eq!((buckets[1].total, buckets[2].total, buckets[3].total), (0, 0, 1));
+    assert_eq!(buckets.last().unwrap().total, 1);
+    assert_eq!(series.peak().unwrap().start, buckets[0].start);
+
+    let rendered = render_timeline(&series);
+    assert!(rendered.starts_with("2024-01-15 10:00:00 |########################################| 2 (1 errors)\n"));
+    assert!(rendered.contains("2024-01-15 10:20:00 ... 284 empty buckets\n"));
+
+    assert_eq!("hour".parse::<BucketWidth>(), Ok(BucketWidth::HOUR));
+    assert_eq!("30s".parse::<BucketWidth>().unwrap().to_string(), "30s");
+    assert!("5x".parse::<BucketWidth>().is_err());
+    assert!("0m".parse::<BucketWidth>().is_err());
+}
+
+#[test]
+fn timeline_merge_rebuckets_different_widths() {
+    let path = Path::new("dummy.log");
+    let stats = |width: &str, lines: &[&str]| {
+        let mut stats = Statistics::new().with_timeline(width.parse().unwrap());
+        for line in lines {
+            stats.add(&parse_log_line(line, path, 1).unwrap());
+        }
+        stats
+    };
+    let minutes = [
+        "2024-01-15 10:00:10 [ERROR] db: a",
+        "2024-01-15 10:01:30 [INFO] app: b",
+        "2024-01-15 10:04:00 [INFO] app: c",
+    ];
+
+    // One width is a multiple of the other: the coarser one is used
+    let mut merged = stats("1m", &minutes);
+    merged.merge(&stats("5m", &["2024-01-15 10:07:00 [INFO] app: d"]));
+    let timeline = merged.timeline.as_ref().unwrap();
+    assert_eq!(timeline.width(), BucketWidth::FIVE_MINUTES);
+    let totals: Vec<usize> = timeline.buckets().map(|b| b.total).collect();
+    assert_eq!(totals, vec![3, 1]);
+    assert_eq!(timeline.buckets().next().unwrap().errors(), 1);
+
+    // Otherwise the narrowest width both divide
+    let mut merged = stats("2m", &minutes);
+    merged.merge(&stats("3m", &["2024-01-15 10:07:00 [INFO] app: d"]));
+    let timeline = merged.timeline.as_ref().unwrap();
+    assert_eq!(timeline.width().to_string(), "6m");
+    let totals: Vec<usize> = timeline.buckets().map(|b| b.total).collect();
+    assert_eq!(totals, vec![3, 1]);
+}
+
+#[test]
+fn timeline_skips_timestamps_outside_supported_range() {
+    let path = Path::new("dummy.log");
+    // In range as wall-clock time, but 1969 / 10000 in UTC
+    for line in ["1970-01-01T00:10:00+01:00 [INFO] app: a", "9999-12-31T23:59:59-01:00 [INFO] app: b"] {
+        assert!(parse_log_line(line, path, 1).is_err());
+    }
+
+    // Hand-built timestamps bypass that check and must not panic when bucketed
+    let entry = |year, month, day, hour, minute, second, offset_minutes| LogEntry {
+        timestamp: DateTime { year, month, day, hour, minute, second, nanosecond: 0, offset_minutes },
+        level: LogLevel::Info,
+        component: "app".to_string(),
+        message: "x".to_string(),
+        source_file: PathBuf::from("dummy.log"),
+        fields: Default::default(),
+    };
+    for width in ["1m", "day"] {
+        let mut series = TimeSeries::new(width.parse().unwrap());
+        series.add(&entry(1970, 1, 1, 0, 10, 0, 60));
+        series.add(&entry(9999, 12, 31, 23, 59, 59, -60));
+        series.add(&entry(2024, 1, 15, 10, 0, 0, 0));
+        assert_eq!(series.buckets().count(), 1);
+        assert_eq!(series.non_empty_buckets().map(|bucket| bucket.total).sum::<usize>(), 1);
+    }
+}
+
+#[test]
+fn timeline_parallel_stats_only_matches_sequential() {
+    let walk = WalkOptions { recursive: true, ..WalkOptions::default() };
+    let mut sequential = LogAnalyzer::new().with_walk_options(walk.clone()).with_timeline(BucketWidth::MINUTE).with_workers(1);
+    sequential.process_directory(Path::new("tests/fixtures")).unwrap();
+
+    let mut parallel = LogAnalyzer::new()
+        .with_walk_options(walk)
+        .with_timeline(BucketWidth::MINUTE)
+        .with_workers(3)
+        .with_statistics_only(true);
+    parallel.process_directory(Path::new("tests/fixtures")).unwrap();
+
+    let timeline = sequential.statistics_ref().timeline.as_ref().unwrap();
+    assert_eq!(timeline.non_empty_buckets().map(|b| b.total).sum::<usize>(), sequential.statistics().total_entries);
+    assert_eq!(parallel.statistics(), sequential.statistics());
+}
+
+// ---------------- TEMPLATE TESTS ----------------
+
+#[test]
+fn template_masks_variable_tokens() {
+    assert_eq!(message_template("Failed to mount filesystem /dev/sda1"), "Failed to mount filesystem <PATH>");
+    assert_eq!(message_template("Failed to mount filesystem /dev/sdb2"), "Failed to mount filesystem <PATH>");
+    assert_eq!(
+        message_template("Request 550e8400-e29b-41d4-a716-446655440000 from 10.0.0.7:51234 took 250ms"),
+        "Request <UUID> from <IP> took <NUM>"
+    );
+    assert_eq!(message_template("retry (attempt=3, id=0x1f) on [fe80::1]"), "retry (attempt=<NUM>, id=<HEX>) on [<IP>]");
+    assert_eq!(message_template("commit a3f5e9b2c4d1 by deadbeef:  user:alice"), "commit <HEX> by deadbeef: user:alice");
+}
+
+#[test]
+fn template_miner_counts_and_merges() {
+    let path = Path::new("dummy.log");
+    let lines = [
+        "2024-01-15 10:00:05 [ERROR] storage: Failed to mount filesystem /dev/sdb2",
+        "2024-01-15 10:00:01 [WARN] storage: Failed to mount filesystem /dev/sda1",
+        "2024-01-15 10:00:09 [ERROR] storage: Failed to mount filesystem /dev/sdc1",
+        "2024-01-15 10:00:02 [INFO] app: Listening on port 8080",
+    ];
+    let entries: Vec<LogEntry> = lines.iter().enumerate().map(|(i, l)| parse_log_line(l, path, i + 1).unwrap()).collect();
+
+    let mut miner = TemplateMiner::new();
+    entries.iter().for_each(|e| miner.add(e));
+    assert_eq!(miner.len(), 2);
+
+    let top = miner.top(1);
+    assert_eq!(top[0].template, "Failed to mount filesystem <PATH>");
+    assert_eq!(top[0].count, 3);
+    assert_eq!(top[0].by_level.get(&LogLevel::Error), Some(&2));
+    assert_eq!(top[0].first_seen.to_string(), "2024-01-15 10:00:01");
+    assert_eq!(top[0].last_seen.to_string(), "2024-01-15 10:00:09");
+    assert_eq!(top[0].example, "Failed to mount filesystem /dev/sda1");
+
+    // Merging in any split is the same as one pass
+    let mut single = Statistics::new().with_templates();
+    entries.iter().for_each(|e| single.add(e));
+    for split in 0..=entries.len() {
+        let mut merged = single.empty_like();
+        let mut rest = single.empty_like();
+        entries[..split].iter().for_each(|e| merged.add(e));
+        entries[split..].iter().for_each(|e| rest.add(e));
+        merged.merge(&rest);
+        assert_eq!(merged, single);
+    }
+}
+
+// ---------------- ANOMALY TESTS ----------------
+
+#[test]
+fn anomaly_flags_error_burst_and_quiet_minute() {
+    let path = Path::new("dummy.log");
+    let mut series = TimeSeries::new(BucketWidth::MINUTE);
+    for minute in 0..40 {
+        // A steady 20 entries a minute, nothing at 10:36, and 20 extra errors at 10:30 and 10:31
+        let normal = if minute == 36 { 0 } else { 20 };
+        for i in 0..normal {
+            let line = format!("2024-01-15 10:{:02}:{:02} [INFO] app: request {} served", minute, i, i);
+            series.add(&parse_log_line(&line, path, 1).unwrap());
+        }
+        if minute == 30 || minute == 31 {
+            for i in 0..20 {
+                let line = format!("2024-01-15 10:{:02}:{:02} [ERROR] db: connection {} refused", minute, 30 + i, i);
+                series.add(&parse_log_line(&line, path, 1).unwrap());
+            }
+        }
+    }
+
+    let anomalies = detect_anomalies(&series, &AnomalyConfig::default());
+    let summary: Vec<(AnomalyKind, String, String, usize)> =
+        anomalies.iter().map(|a| (a.kind, a.start.to_string(), a.end.to_string(), a.observed)).collect();
+    assert_eq!(
+        summary,
+        vec![
+            (AnomalyKind::VolumeSpike, "2024-01-15 10:30:00".to_string(), "2024-01-15 10:32:00".to_string(), 80),
+            (AnomalyKind::ErrorSpike, "2024-01-15 10:30:00".to_string(), "2024-01-15 10:32:00".to_string(), 40),
+            (AnomalyKind::VolumeDrop, "2024-01-15 10:36:00".to_string(), "2024-01-15 10:37:00".to_string(), 0),
+        ]
+    );
+
+    let errors = &anomalies[1];
+    assert_eq!(errors.top_components, vec![("db".to_string(), 40)]);
+    assert_eq!(errors.expected, 0.0);
+    assert!(errors.score > 10.0);
+    assert_eq!(errors.samples.len(), 3);
+    assert_eq!(errors.samples[0].message, "connection 0 refused");
+    assert!(errors.samples.iter().all(|s| s.level == LogLevel::Error));
+    assert!(anomalies[2].score < -3.5);
+}
+
+// ---------------- GAP TESTS ----------------
+
+#[test]
+fn gaps_found_by_threshold_and_usual_interval() {
+    let path = Path::new("dummy.log");
+    let mut lines = vec!["2024-01-15 10:00:00 [INFO] db: connected".to_string()];
+    // auth beats every 2 seconds until 10:06, except for a minute from 10:01
+    for second in (0..=360).step_by(2).filter(|s| !(61..120).contains(s)) {
+        lines.push(format!("2024-01-15 10:{:02}:{:02} [INFO] auth: heartbeat", second / 60, second % 60));
+    }
+    let entries: Vec<LogEntry> = lines.iter().map(|l| parse_log_line(l, path, 1).unwrap()).collect();
+
+    // Activity merges exactly, whatever the split
+    let mut activity = ComponentActivity::new();
+    entries.iter().for_each(|e| activity.add(e));
+    let mut merged = ComponentActivity::new();
+    let mut rest = ComponentActivity::new();
+    entries[..100].iter().for_each(|e| merged.add(e));
+    entries[100..].iter().for_each(|e| rest.add(e));
+    merged.merge(&rest);
+    assert_eq!(merged, activity);
+
+    let end = entries.iter().map(|e| e.timestamp.clone()).max();
+    let gaps = find_gaps(&activity, &GapConfig::default(), end.as_ref());
+    let summary: Vec<(&str, String, String, bool)> =
+        gaps.iter().map(|g| (g.component.as_str(), g.start.to_string(), format_duration(g.duration), g.trailing)).collect();
+    assert_eq!(
+        summary,
+        vec![
+            // 60s is well under the 5m threshold, but 30 times auth's usual 2s
+            ("auth", "2024-01-15 10:01:00".to_string(), "1m".to_string(), false),
+            // db logged once: no usual interval, but silent for 6 minutes until the input ended
+            ("db", "2024-01-15 10:00:00".to_string(), "6m".to_string(), true),
+        ]
+    );
+    assert_eq!(gaps[0].usual, Some(Duration::from_secs(2)));
+
+    assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+    assert_eq!(format_duration(Duration::from_secs(90_061)), "1d 1h 1m 1s");
+    assert!(parse_duration("5 minutes").is_err());
+}
+
+#[test]
+fn gaps_skip_silences_outside_supported_range() {
+    let entry = |hour, offset_minutes| LogEntry {
+        timestamp: DateTime { year: 1970, month: 1, day: 1, hour, minute: 10, second: 0, nanosecond: 0, offset_minutes },
+        level: LogLevel::Info,
+        component: "app".to_string(),
+        message: "x".to_string(),
+        source_file: PathBuf::from("dummy.log"),
+        fields: Default::default(),
+    };
+    // Hand-built: 1970-01-01T00:10:00+01:00 is in 1969 as an instant
+    let mut activity = ComponentActivity::new();
+    activity.add(&entry(0, 60));
+    activity.add(&entry(2, 0));
+
+    let end = entry(3, 0).timestamp;
+    let gaps = find_gaps(&activity, &GapConfig::default(), Some(&end));
+    let summary: Vec<(String, bool)> = gaps.iter().map(|g| (g.start.to_string(), g.trailing)).collect();
+    assert_eq!(summary, vec![("1970-01-01 02:10:00".to_string(), true)]);
+}
+
+#[test]
+fn gaps_activity_stays_bounded() {
+    let entry = |second: i64| LogEntry {
+        timestamp: DateTime::from_unix_seconds(1_705_312_800 + second).unwrap(),
+        level: LogLevel::Info,
+        component: "app".to_string(),
+        message: "tick".to_string(),
+        source_file: PathBuf::from("dummy.log"),
+        fields: Default::default(),
+    };
+    // Every 10 seconds for about four days, with 10-minute outages at three points
+    let outages = [50_000, 150_000, 300_000];
+    let mut activity = ComponentActivity::new();
+    for second in (0..350_000).step_by(10) {
+        if !outages.iter().any(|&start| (start + 1..start + 600).contains(&second)) {
+            activity.add(&entry(second));
+        }
+    }
+    assert!(serde_json::to_string(&activity).unwrap().len() < 4096);
+
+    let config = GapConfig { threshold: Duration::from_secs(3600), ..GapConfig::default() };
+    let gaps = find_gaps(&activity, &config, None);
+    assert_eq!(gaps.len(), 3);
+    assert!(gaps.iter().all(|g| g.duration == Duration::from_secs(600)));
+    assert_eq!(gaps[0].usual, Some(Duration::from_secs(10)));
+
+    // Overlapping inputs: silent only where both were
+    let mut first = ComponentActivity::new();
+    for second in (0..=1000).step_by(10).filter(|s| !(301..700).contains(s)) {
+        first.add(&entry(second));
+    }
+    let mut second = ComponentActivity::new();
+    second.add(&entry(500));
+    first.merge(&second);
+    let config = GapConfig { threshold: Duration::from_secs(150), ..GapConfig::default() };
+    let durations: Vec<u64> = find_gaps(&first, &config, None).iter().map(|g| g.duration.as_secs()).collect();
+    assert_eq!(durations, vec![200, 200]);
+}
+
+// ---------------- DIFF TESTS ----------------
+
+#[test]
+fn diff_reports_deltas_shares_and_templates() {
+    let path = Path::new("dummy.log");
+    let mut before = Statistics::new().with_templates();
+    for i in 0..100 {
+        let line = format!("2024-01-15 10:00:{:02} [INFO] app: request {} served", i % 60, i);
+        before.add(&parse_log_line(&line, path, 1).unwrap());
+    }
+    before.add(&parse_log_line("2024-01-15 10:01:00 [WARN] cache: cache cleared", path, 1).unwrap());
+
+    let mut after = Statistics::new().with_templates();
+    for i in 0..70 {
+        let line = format!("2024-01-16 10:00:{:02} [INFO] app: request {} served", i % 60, i);
+        after.add(&parse_log_line(&line, path, 1).unwrap());
+    }
+    for i in 0..30 {
+        let line = format!("2024-01-16 10:01:{:02} [ERROR] db: connection to 10.0.0.{} refused", i, i);
+        after.add(&parse_log_line(&line, path, 1).unwrap());
+    }
+
+    let diff = compare(&before, &after, &DiffConfig::default());
+    assert_eq!((diff.total_before, diff.total_after, diff.total_delta()), (101, 100, -1));
+    assert_eq!((diff.error_rate_before, diff.error_rate_after), (0.0, 0.3));
+
+    let levels: Vec<(&str, usize, usize)> = diff.by_level.iter().map(|c| (c.name.as_str(), c.before, c.after)).collect();
+    assert_eq!(levels, vec![("Info", 100, 70), ("Warn", 1, 0), ("Error", 0, 30)]);
+    let components: Vec<(&str, i64)> = diff.by_component.iter().map(|c| (c.name.as_str(), c.delta())).collect();
+    assert_eq!(components, vec![("app", -30), ("db", 30), ("cache", -1)]);
+
+    // cache's one entry is not a significant shift
+    let shares: Vec<&str> = diff.share_changes.iter().map(|c| c.component.as_str()).collect();
+    assert_eq!(shares, vec!["db", "app"]);
+    assert!(diff.share_changes[0].z_score > 3.0 && diff.share_changes[1].z_score < -3.0);
+
+    let new = diff.new_templates.as_ref().unwrap();
+    assert_eq!(new.len(), 1);
+    assert_eq!((new[0].template.as_str(), new[0].count), ("connection to <IP> refused", 30));
+    let gone = diff.gone_templates.as_ref().unwrap();
+    assert_eq!(gone.iter().map(|t| t.template.as_str()).collect::<Vec<_>>(), vec!["cache cleared"]);
+}
+
+// ---------------- SNAPSHOT TESTS ----------------
+
+#[test]
+fn snapshot_round_trips_statistics() {
+    let walk = WalkOptions { recursive: true, ..WalkOptions::default() };
+    let mut analyzer = LogAnalyzer::new()
+        .with_walk_options(walk)
+        .with_timeline(BucketWidth::FIVE_MINUTES)
+        .with_templates()
+        .with_activity();
+    analyzer.process_directory(Path::new("tests/fixtures")).unwrap();
+    let stats = analyzer.statistics();
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("stats.json");
+    save_snapshot(&stats, &path).unwrap();
+    let loaded = load_snapshot(&path).unwrap();
+    assert_eq!(loaded, stats);
+
+    // Keys are sorted, so saving again gives the same file
+    let again = dir.path().join("again.json");
+    save_snapshot(&loaded, &again).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), std::fs::read_to_string(&again).unwrap());
+
+    // Offsets and fractions survive, not just the instant
+    let time: DateTime = "2024-01-15T10:23:45.120+02:00".parse().unwrap();
+    let json = serde_json::to_string(&time).unwrap();
+    assert_eq!(json, "\"2024-01-15 10:23:45.120+02:00\"");
+    let back: DateTime = serde_json::from_str(&json).unwrap();
+    assert_eq!((back.hour, back.nanosecond, back.offset_minutes), (10, 120_000_000, 120));
+    assert_eq!(serde_json::from_str::<LogLevel>("\"ERROR\"").unwrap(), LogLevel::Error);
+}
+
+#[test]
+fn snapshot_rejects_other_schema_versions() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("future.json");
+    std::fs::write(&path, r#"{"schema_version": 99, "statistics": {"whatever": true}}"#).unwrap();
+    match load_snapshot(&path) {
+        Err(SnapshotError::UnsupportedVersion { found, .. }) => assert_eq!(found, 99),
+        other => panic!("expected a version error, got {:?}", other),
+    }
+
+    std::fs::write(&path, r#"{"schema_version": 1, "statistics": {"total_entries": "many"}}"#).unwrap();
+    assert!(matches!(load_snapshot(&path), Err(SnapshotError::InvalidSnapshot { .. })));
+    assert!(matches!(load_snapshot(&dir.path().join("missing.json")), Err(SnapshotError::IoError { .. })));
+}
+
+#[test]
+fn snapshot_pair_compared_without_logs() {
+    let path = Path::new("dummy.log");
+    let mut before = Statistics::new().with_templates();
+    let mut after = Statistics::new().with_templates();
+    for i in 0..20 {
+        let line = format!("2024-01-15 10:00:{:02} [INFO] app: request {} served", i, i);
+        before.add(&parse_log_line(&line, path, 1).unwrap());
+        let line = format!("2024-01-16 10:00:{:02} [ERROR] db: connection {} refused", i, i);
+        after.add(&parse_log_line(&line, path, 1).unwrap());
+    }
+    let dir = tempfile::tempdir().unwrap();
+    let (before_path, after_path) = (dir.path().join("before.json"), dir.path().join("after.json"));
+    save_snapshot(&before, &before_path).unwrap();
+    save_snapshot(&after, &after_path).unwrap();
+
+    let run = |args: &[&std::ffi::OsStr]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_log-analyzer")).args(args).output().unwrap()
+    };
+    let output = run(&[
+        "--baseline-snapshot".as_ref(),
+        before_path.as_os_str(),
+        "--snapshot".as_ref(),
+        after_path.as_os_str(),
+        "--format".as_ref(),
+        "json".as_ref(),
+    ]);
+    assert!(output.status.success());
+    let printed: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(printed, serde_json::to_value(compare(&before, &after, &DiffConfig::default())).unwrap());
+
+    let output = run(&["--snapshot".as_ref(), after_path.as_os_str()]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "--snapshot needs a --baseline-snapshot to compare against\n");
+}
+
+#[test]
+fn report_json_keeps_its_layout() {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_log-analyzer"))
+        .args(["tests/fixtures/simple.log", "--format", "json", "--gaps", "--gap-threshold", "3s", "--templates"])
+        .args(["--timeline", "1s"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    let keys: Vec<&str> = report.as_object().unwrap().keys().map(String::as_str).collect();
+    assert_eq!(
+        keys,
+        vec![
+            "total_entries",
+            "error_rate",
+            "peak_hour",
+            "most_active_component",
+            "entries_by_level",
+            "entries_by_component",
+            "period",
+            "timeline",
+            "templates",
+            "gaps",
+            "parse_errors",
+        ]
+    );
+    assert_eq!(report["period"]["start"], "2024-01-15 10:00:00");
+    assert_eq!(report["period"]["end"], "2024-01-15 10:00:10");
+    assert_eq!(report["entries_by_level"]["Error"], 1);
+
+    // Every second from the first entry to the last, the quiet ones with zeros
+    assert_eq!(report["timeline"]["bucket_width"], "1s");
+    let buckets = report["timeline"]["buckets"].as_array().unwrap();
+    assert_eq!(buckets.len(), 11);
+    let bucket_keys: Vec<&str> = buckets[3].as_object().unwrap().keys().map(String::as_str).collect();
+    assert_eq!(bucket_keys, vec!["start", "total", "by_level", "by_component"]);
+    assert_eq!(buckets[3]["total"], 0);
+    assert_eq!(buckets.iter().map(|b| b["total"].as_u64().unwrap()).sum::<u64>(), 5);
+
+    // The most frequent templates, as a list
+    let templates = report["templates"].as_array().unwrap();
+    assert_eq!(templates.len(), 5);
+    let template_keys: Vec<&str> = templates[0].as_object().unwrap().keys().map(String::as_str).collect();
+    assert_eq!(template_keys, vec!["template", "count", "by_level", "first_seen", "last_seen", "example"]);
+
+    assert_eq!(report["gaps"][0]["component"], "app");
+    assert_eq!(report["gaps"][0]["duration_secs"], 10);
+    assert_eq!(report["gaps"][0]["trailing"], true);
+}
+
+// ---------------- DIAGNOSTICS TESTS ----------------
+
+#[test]
+fn diagnostics_group_by_kind_with_carets() {
+    let mut analyzer = LogAnalyzer::new();
+    analyzer.process_file(Path::new("tests/fixtures/malformed.log")).unwrap();
+
+    let out = render_diagnostics(analyzer.parse_errors(), 1);
+    assert!(out.starts_with("error[bad-timestamp]: 2 occurrences (showing 1)\n"));
+    assert!(out.contains(" --> tests/fixtures/malformed.log:2\n"));
+    assert!(out.contains("5 | 2024-01-15 10:00:00 [UNKNOWN] bad: Invalid level\n"));
+    assert!(out.contains("  |                     ^^^^^^^^^ Invalid log level"));
+    assert!(out.contains("  = 1 more bad-timestamp errors not shown"));
+    assert!(out.contains("parse error summary: 4 errors"));
+}